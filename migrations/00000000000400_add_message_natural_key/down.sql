@@ -0,0 +1,2 @@
+DROP INDEX public.messages_natural_key;
+ALTER TABLE public.messages DROP COLUMN occurrence;
@@ -0,0 +1,14 @@
+ALTER TABLE public.messages ADD COLUMN occurrence SMALLINT NOT NULL DEFAULT 0;
+
+-- Number messages which were already stored more than once, so the unique index can be created
+-- without deleting anything
+UPDATE public.messages m
+SET occurrence = d.row_number - 1
+FROM (
+  SELECT id, row_number() OVER (PARTITION BY channel_id, user_id, sent_at, message ORDER BY id)
+  FROM public.messages
+) d
+WHERE m.id = d.id AND d.row_number > 1;
+
+CREATE UNIQUE INDEX messages_natural_key
+  ON public.messages (channel_id, user_id, sent_at, md5(message), occurrence);
@@ -0,0 +1,2 @@
+ALTER TABLE public.messages DROP COLUMN import_id;
+DROP TABLE public.imports;
@@ -0,0 +1,23 @@
+CREATE TABLE public.imports
+(
+  id SERIAL NOT NULL,
+  source_path CHARACTER VARYING NOT NULL,
+  content_hash CHARACTER VARYING (64) NOT NULL,
+  byte_size BIGINT NOT NULL,
+  started_at TIMESTAMP NOT NULL,
+  finished_at TIMESTAMP,
+  parser CHARACTER VARYING (100) NOT NULL,
+  message_count INT NOT NULL DEFAULT 0,
+  duplicate_count INT NOT NULL DEFAULT 0,
+  PRIMARY KEY (id)
+);
+
+CREATE INDEX imports_content_hash ON public.imports (content_hash);
+
+-- Deleting an import removes all messages it produced
+ALTER TABLE public.messages ADD COLUMN import_id INT,
+  ADD CONSTRAINT message_import_id_fk FOREIGN KEY (import_id) REFERENCES public.imports (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE;
+
+CREATE INDEX messages_import_id ON public.messages (import_id);
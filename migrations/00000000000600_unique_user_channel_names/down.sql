@@ -0,0 +1,2 @@
+ALTER TABLE public.channels DROP CONSTRAINT channels_name_key;
+ALTER TABLE public.users DROP CONSTRAINT users_name_key;
@@ -0,0 +1,25 @@
+-- Merge users and channels which were created more than once into the one with the lowest id
+UPDATE public.messages m
+SET user_id = d.keep_id
+FROM (
+  SELECT id, min(id) OVER (PARTITION BY name) AS keep_id FROM public.users
+) d
+WHERE m.user_id = d.id AND d.id <> d.keep_id;
+
+DELETE FROM public.users u
+USING public.users keep
+WHERE u.name = keep.name AND u.id > keep.id;
+
+UPDATE public.messages m
+SET channel_id = d.keep_id
+FROM (
+  SELECT id, min(id) OVER (PARTITION BY name) AS keep_id FROM public.channels
+) d
+WHERE m.channel_id = d.id AND d.id <> d.keep_id;
+
+DELETE FROM public.channels c
+USING public.channels keep
+WHERE c.name = keep.name AND c.id > keep.id;
+
+ALTER TABLE public.users ADD CONSTRAINT users_name_key UNIQUE (name);
+ALTER TABLE public.channels ADD CONSTRAINT channels_name_key UNIQUE (name);
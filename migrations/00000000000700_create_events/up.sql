@@ -0,0 +1,22 @@
+CREATE TABLE public.events
+(
+  id SERIAL NOT NULL,
+  channel_id INT,
+  sent_at TIMESTAMP NOT NULL,
+  message TEXT NOT NULL,
+  import_id INT,
+  PRIMARY KEY (id),
+  CONSTRAINT event_channel_id_fk FOREIGN KEY (channel_id) REFERENCES public.channels (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT event_import_id_fk FOREIGN KEY (import_id) REFERENCES public.imports (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE
+);
+
+-- Events logged before a channel was joined have no channel, which would never conflict in a plain
+-- unique index
+CREATE UNIQUE INDEX events_natural_key
+  ON public.events (COALESCE(channel_id, 0), sent_at, md5(message));
+
+CREATE INDEX events_import_id ON public.events (import_id);
@@ -0,0 +1 @@
+DROP TABLE public.messages_staging;
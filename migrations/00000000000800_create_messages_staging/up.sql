@@ -0,0 +1,16 @@
+-- Messages loaded with COPY pass through this table on their way into messages. Its rows are only
+-- kept until they are moved, so it is not written to the WAL.
+CREATE UNLOGGED TABLE public.messages_staging
+(
+  -- Backend id of the connection which loaded the row
+  token INT NOT NULL,
+  user_id INT,
+  channel_id INT,
+  message TEXT,
+  sent_at TIMESTAMP,
+  flags SMALLINT[],
+  occurrence SMALLINT,
+  import_id INT
+);
+
+CREATE INDEX messages_staging_token ON public.messages_staging (token);
@@ -0,0 +1 @@
+DROP TABLE public.moderation_actions;
@@ -0,0 +1,32 @@
+CREATE TABLE public.moderation_actions
+(
+  id SERIAL NOT NULL,
+  channel_id INT NOT NULL,
+  target_id INT NOT NULL,
+  moderator_id INT,
+  kind SMALLINT NOT NULL,
+  duration INT,
+  reason TEXT,
+  sent_at TIMESTAMP NOT NULL,
+  import_id INT,
+  PRIMARY KEY (id),
+  CONSTRAINT moderation_action_channel_id_fk FOREIGN KEY (channel_id) REFERENCES public.channels (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT moderation_action_target_id_fk FOREIGN KEY (target_id) REFERENCES public.users (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT moderation_action_moderator_id_fk FOREIGN KEY (moderator_id) REFERENCES public.users (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE SET NULL,
+  CONSTRAINT moderation_action_import_id_fk FOREIGN KEY (import_id) REFERENCES public.imports (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX moderation_actions_natural_key
+  ON public.moderation_actions (channel_id, target_id, sent_at, kind);
+
+CREATE INDEX moderation_actions_target_id ON public.moderation_actions (target_id);
+CREATE INDEX moderation_actions_moderator_id ON public.moderation_actions (moderator_id);
+CREATE INDEX moderation_actions_import_id ON public.moderation_actions (import_id);
@@ -0,0 +1,3 @@
+DROP VIEW public.visible_messages;
+DROP INDEX public.users_lower_name;
+ALTER TABLE public.messages DROP COLUMN deleted_by_action, DROP COLUMN deleted_at;
@@ -0,0 +1,16 @@
+-- Messages cleared from chat by a timeout or ban
+ALTER TABLE public.messages ADD COLUMN deleted_at TIMESTAMP,
+  ADD COLUMN deleted_by_action INT,
+  ADD CONSTRAINT message_deleted_by_action_fk FOREIGN KEY (deleted_by_action)
+  REFERENCES public.moderation_actions (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE SET NULL;
+
+CREATE INDEX messages_deleted_by_action ON public.messages (deleted_by_action);
+
+-- Targets of timeouts and bans are matched to users regardless of case
+CREATE INDEX users_lower_name ON public.users (lower(name));
+
+-- Messages as they were visible on stream
+CREATE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
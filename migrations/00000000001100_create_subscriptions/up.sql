@@ -0,0 +1,33 @@
+CREATE TABLE public.subscriptions
+(
+  id SERIAL NOT NULL,
+  channel_id INT NOT NULL,
+  subscriber_id INT NOT NULL,
+  recipient_id INT,
+  kind SMALLINT NOT NULL,
+  tier SMALLINT NOT NULL,
+  months INT NOT NULL DEFAULT 1,
+  message TEXT,
+  sent_at TIMESTAMP NOT NULL,
+  import_id INT,
+  PRIMARY KEY (id),
+  CONSTRAINT subscription_channel_id_fk FOREIGN KEY (channel_id) REFERENCES public.channels (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT subscription_subscriber_id_fk FOREIGN KEY (subscriber_id) REFERENCES public.users (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT subscription_recipient_id_fk FOREIGN KEY (recipient_id) REFERENCES public.users (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT subscription_import_id_fk FOREIGN KEY (import_id) REFERENCES public.imports (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE
+);
+
+-- A gifter can give several subs within the same second, which differ by recipient
+CREATE UNIQUE INDEX subscriptions_natural_key
+  ON public.subscriptions (channel_id, subscriber_id, sent_at, kind, COALESCE(recipient_id, 0));
+
+CREATE INDEX subscriptions_channel_sent_at ON public.subscriptions (channel_id, sent_at);
+CREATE INDEX subscriptions_import_id ON public.subscriptions (import_id);
@@ -0,0 +1,5 @@
+DROP VIEW public.visible_messages;
+ALTER TABLE public.messages_staging DROP COLUMN kind;
+ALTER TABLE public.messages DROP COLUMN kind;
+CREATE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
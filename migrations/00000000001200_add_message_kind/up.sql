@@ -0,0 +1,7 @@
+-- Chat, action, whisper or notice, see MessageKind
+ALTER TABLE public.messages ADD COLUMN kind SMALLINT NOT NULL DEFAULT 1;
+
+ALTER TABLE public.messages_staging ADD COLUMN kind SMALLINT;
+
+CREATE OR REPLACE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
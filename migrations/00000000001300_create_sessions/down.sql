@@ -0,0 +1,6 @@
+DROP VIEW public.visible_messages;
+ALTER TABLE public.messages_staging DROP COLUMN session_id;
+ALTER TABLE public.messages DROP COLUMN session_id;
+CREATE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
+DROP TABLE public.sessions;
@@ -0,0 +1,40 @@
+-- A run of the logging client, from "Log started" to "Log closed"
+CREATE TABLE public.sessions
+(
+  id SERIAL NOT NULL,
+  channel_id INT,
+  started_at TIMESTAMP NOT NULL,
+  -- NULL if the client stopped without closing the log, e.g. because it crashed
+  ended_at TIMESTAMP,
+  last_seen_at TIMESTAMP,
+  message_count INT NOT NULL DEFAULT 0,
+  import_id INT,
+  -- Path of the log file the session was recorded in
+  source_path TEXT,
+  PRIMARY KEY (id),
+  CONSTRAINT session_channel_id_fk FOREIGN KEY (channel_id) REFERENCES public.channels (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE,
+  CONSTRAINT session_import_id_fk FOREIGN KEY (import_id) REFERENCES public.imports (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_channel_started_at ON public.sessions (channel_id, started_at);
+CREATE INDEX sessions_import_id ON public.sessions (import_id);
+
+-- A session is identified by the file it was logged in and its start. The channel is only known
+-- once the session has joined one, and a log file only ever records one session starting at a time.
+CREATE UNIQUE INDEX sessions_source_started_at ON public.sessions (source_path, started_at);
+
+ALTER TABLE public.messages ADD COLUMN session_id INT,
+  ADD CONSTRAINT message_session_id_fk FOREIGN KEY (session_id) REFERENCES public.sessions (id) MATCH SIMPLE
+  ON UPDATE CASCADE
+  ON DELETE SET NULL;
+
+CREATE INDEX messages_session_id ON public.messages (session_id);
+
+ALTER TABLE public.messages_staging ADD COLUMN session_id INT;
+
+CREATE OR REPLACE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
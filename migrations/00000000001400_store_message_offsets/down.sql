@@ -0,0 +1,9 @@
+DROP VIEW public.visible_messages;
+ALTER TABLE public.messages_staging
+  ALTER COLUMN sent_at TYPE TIMESTAMP USING sent_at AT TIME ZONE 'UTC',
+  DROP COLUMN utc_offset;
+ALTER TABLE public.messages ALTER COLUMN sent_at TYPE TIMESTAMP USING sent_at AT TIME ZONE 'UTC',
+  ALTER COLUMN deleted_at TYPE TIMESTAMP USING deleted_at AT TIME ZONE 'UTC',
+  DROP COLUMN utc_offset;
+CREATE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
@@ -0,0 +1,14 @@
+-- Existing times were stored in UTC without their zone
+DROP VIEW public.visible_messages;
+
+ALTER TABLE public.messages ALTER COLUMN sent_at TYPE TIMESTAMPTZ USING sent_at AT TIME ZONE 'UTC',
+  ALTER COLUMN deleted_at TYPE TIMESTAMPTZ USING deleted_at AT TIME ZONE 'UTC',
+  -- Offset from UTC in seconds the message was logged with, unknown for earlier imports
+  ADD COLUMN utc_offset INT;
+
+ALTER TABLE public.messages_staging
+  ALTER COLUMN sent_at TYPE TIMESTAMPTZ USING sent_at AT TIME ZONE 'UTC',
+  ADD COLUMN utc_offset INT;
+
+CREATE VIEW public.visible_messages AS
+  SELECT * FROM public.messages WHERE deleted_at IS NULL;
@@ -0,0 +1,5 @@
+CREATE TABLE channels
+(
+  id INTEGER PRIMARY KEY NOT NULL,
+  name VARCHAR NOT NULL
+);
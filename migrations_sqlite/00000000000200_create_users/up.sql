@@ -0,0 +1,5 @@
+CREATE TABLE users
+(
+  id INTEGER PRIMARY KEY NOT NULL,
+  name VARCHAR(100) NOT NULL
+);
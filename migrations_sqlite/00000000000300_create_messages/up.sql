@@ -0,0 +1,10 @@
+-- Flags are stored as comma separated MessageFlag codes, e.g. '2,3'
+CREATE TABLE messages
+(
+  id INTEGER PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  channel_id INTEGER NOT NULL REFERENCES channels (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  message TEXT NOT NULL,
+  sent_at TIMESTAMP NOT NULL,
+  flags TEXT NOT NULL
+);
@@ -0,0 +1,13 @@
+-- SQLite cannot drop columns, so the table is rebuilt without it
+CREATE TABLE messages_without_kind
+(
+  id INTEGER PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  channel_id INTEGER NOT NULL REFERENCES channels (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  message TEXT NOT NULL,
+  sent_at TIMESTAMP NOT NULL,
+  flags TEXT NOT NULL
+);
+INSERT INTO messages_without_kind SELECT id, user_id, channel_id, message, sent_at, flags FROM messages;
+DROP TABLE messages;
+ALTER TABLE messages_without_kind RENAME TO messages;
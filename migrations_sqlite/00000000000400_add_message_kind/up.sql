@@ -0,0 +1,2 @@
+-- Chat, action, whisper or notice as MessageKind code
+ALTER TABLE messages ADD COLUMN kind INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,14 @@
+-- SQLite cannot drop columns, so the table is rebuilt without it
+CREATE TABLE messages_without_offset
+(
+  id INTEGER PRIMARY KEY NOT NULL,
+  user_id INTEGER NOT NULL REFERENCES users (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  channel_id INTEGER NOT NULL REFERENCES channels (id) ON UPDATE CASCADE ON DELETE RESTRICT,
+  message TEXT NOT NULL,
+  sent_at TIMESTAMP NOT NULL,
+  flags TEXT NOT NULL,
+  kind INTEGER NOT NULL DEFAULT 1
+);
+INSERT INTO messages_without_offset SELECT id, user_id, channel_id, message, sent_at, flags, kind FROM messages;
+DROP TABLE messages;
+ALTER TABLE messages_without_offset RENAME TO messages;
@@ -0,0 +1,2 @@
+-- Offset from UTC in seconds the message was logged with, sent_at is stored in UTC
+ALTER TABLE messages ADD COLUMN utc_offset INTEGER;
@@ -1,39 +1,207 @@
+extern crate chrono;
+extern crate chrono_tz;
 extern crate diesel;
 extern crate error_chain;
 #[macro_use] extern crate quicli;
 extern crate twitch_archiver;
 extern crate core;
 
+use chrono::Duration;
+use chrono_tz::Tz;
 use diesel::Connection;
 use diesel::pg::PgConnection;
+use diesel::sqlite::SqliteConnection;
 use error_chain::ChainedError;
 use quicli::prelude::*;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufReader};
-use std::path::PathBuf;
-use twitch_archiver::collector::PgCollector;
-use twitch_archiver::parser::{ChattyParser, LogParser};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use twitch_archiver::input::{find_log_files, open_log, Fallback, FileHints, HintPatterns, LogFiles, LogSource};
+use twitch_archiver::collector::{Collector, CsvCollector, JsonLinesCollector, LoadStrategy, NullCollector,
+                                 PgCollector, SqliteCollector};
+use twitch_archiver::parser::{AnomalyPolicy, ChattyParser, DateInference, LogParser, ParseMode,
+                              ParseReport, ParserContext, ParserOptions};
+use twitch_archiver::repair::repair;
+use twitch_archiver::report::{coverage, localize, session_spans, subscriptions_per_day, write_csv,
+                              AnomalyRecord, RejectRecord};
 use core::str::FromStr;
 
 
 /// Parse chatty log files and save the data in other formats or databases
 #[derive(Debug, StructOpt)]
-struct Cli {
-    /// Output/Exporting format (pg, csv)
+enum Cli {
+    /// Import log files into a database or export them to a file
+    #[structopt(name = "import")]
+    Import(ImportArgs),
+    /// Report subscriptions per channel and day as CSV
+    #[structopt(name = "subs")]
+    Subs(ReportArgs),
+    /// Report when channels were and were not recorded as CSV
+    #[structopt(name = "gaps")]
+    Gaps(GapsArgs),
+    /// Repair the damage a crash left in a log file
+    #[structopt(name = "repair")]
+    Repair(RepairArgs),
+}
+
+#[derive(Debug, StructOpt)]
+struct ImportArgs {
+    /// Output/Exporting format (pg, sqlite, csv, jsonl)
     #[structopt(long = "output", short = "o")]
     format: Output,
-    /// Database URL if saving in a database
+    /// Database URL if saving in a database, or the file path for SQLite
     #[structopt(long = "database", short = "db")]
     db_url: Option<String>,
-    /// The log file to read
+    /// How messages are loaded into PostgreSQL (insert, copy)
+    #[structopt(long = "load", default_value = "insert")]
+    load: Load,
+    /// Load the ids of all known users and channels before importing into PostgreSQL
+    #[structopt(long = "preload")]
+    preload: bool,
+    /// How many minutes of a user's messages a timeout or ban clears from chat in PostgreSQL
+    #[structopt(long = "cleared-window", default_value = "10")]
+    cleared_window: i64,
+    /// How dates are inferred for time-only timestamps (rollover, bounded)
+    #[structopt(long = "dates", default_value = "rollover")]
+    dates: Dates,
+    /// What to do about timestamps that jump backwards or far ahead (fail, warn, accept)
+    #[structopt(long = "anomalies", default_value = "warn")]
+    anomalies: Anomalies,
+    /// Skip lines that cannot be parsed instead of failing the file at the first of them
+    #[structopt(long = "lenient")]
+    lenient: bool,
+    /// How bytes that are not valid UTF-8 are read (replace, latin1, cp1252)
+    #[structopt(long = "fallback", default_value = "replace")]
+    fallback: Encoding,
+    /// Write all skipped lines to this file as CSV
+    #[structopt(long = "rejects", parse(from_os_str))]
+    rejects: Option<PathBuf>,
+    /// Write all timestamp anomalies to this file as CSV
+    #[structopt(long = "anomaly-report", parse(from_os_str))]
+    anomaly_report: Option<PathBuf>,
+    /// IANA zone the logs were written in, for logs whose UTC offsets are wrong
+    #[structopt(long = "log-zone")]
+    log_zone: Option<Tz>,
+    /// IANA zone times are written in by file based formats, defaults to the logged offsets
+    #[structopt(long = "zone")]
+    zone: Option<Tz>,
+    /// Regex reading the channel, date and zone of a log from its path, replaces the defaults
+    #[structopt(long = "hint-pattern")]
+    hint_patterns: Vec<String>,
+    /// Output file for file based formats, defaults to stdout
+    #[structopt(long = "out", parse(from_os_str))]
+    out: Option<PathBuf>,
+    /// The log files, directories or glob patterns to read
     #[structopt(parse(from_os_str))]
-    file: PathBuf,
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ReportArgs {
+    /// PostgreSQL database URL
+    #[structopt(long = "database", short = "db")]
+    db_url: String,
+    /// IANA zone days are counted in, defaults to UTC
+    #[structopt(long = "zone")]
+    zone: Option<Tz>,
+    /// Output file, defaults to stdout
+    #[structopt(long = "out", parse(from_os_str))]
+    out: Option<PathBuf>,
 }
 
 #[derive(Debug)]
 enum Output {
     Pg,
+    Sqlite,
     Csv,
+    Jsonl,
+}
+
+#[derive(Debug)]
+enum Dates {
+    Rollover,
+    Bounded,
+}
+
+impl FromStr for Dates {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rollover" => Ok(Dates::Rollover),
+            "bounded" => Ok(Dates::Bounded),
+            _ => bail!("Invalid date inference")
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Anomalies {
+    Fail,
+    Warn,
+    Accept,
+}
+
+impl FromStr for Anomalies {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fail" => Ok(Anomalies::Fail),
+            "warn" => Ok(Anomalies::Warn),
+            "accept" => Ok(Anomalies::Accept),
+            _ => bail!("Invalid anomaly policy")
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Encoding {
+    Replace,
+    Latin1,
+    Cp1252,
+}
+
+impl FromStr for Encoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "replace" => Ok(Encoding::Replace),
+            "latin1" | "iso-8859-1" => Ok(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Ok(Encoding::Cp1252),
+            _ => bail!("Invalid fallback encoding")
+        }
+    }
+}
+
+impl Encoding {
+    fn fallback(&self) -> Fallback {
+        match *self {
+            Encoding::Replace => Fallback::Replace,
+            Encoding::Latin1 => Fallback::Latin1,
+            Encoding::Cp1252 => Fallback::Cp1252,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Load {
+    Insert,
+    Copy,
+}
+
+impl FromStr for Load {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "insert" => Ok(Load::Insert),
+            "copy" => Ok(Load::Copy),
+            _ => bail!("Invalid load strategy")
+        }
+    }
 }
 
 impl FromStr for Output {
@@ -42,31 +210,322 @@ impl FromStr for Output {
     fn from_str(s: &str) -> Result<Self> {
         match s {
             "pg" | "postgres" => Ok(Output::Pg),
+            "sqlite" => Ok(Output::Sqlite),
             "csv" => Ok(Output::Csv),
+            "jsonl" | "json-lines" => Ok(Output::Jsonl),
             _ => bail!("Invalid output type")
         }
     }
 }
 
-main!(|args: Cli| {
-    if !args.file.exists() { bail!("File does not exist") };
-    if !args.file.is_file() { bail!("Path is not a file") };
+/// Opens the output file given on the command line, falling back to stdout
+fn open_output(path: Option<PathBuf>) -> Result<Box<Write>> {
+    Ok(match path {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stdout()),
+    })
+}
 
-    println!("Opening log file {:?}", args.file);
-    let file = File::open(args.file)?;
+/// Converts a library error into a printable error including its causes
+fn chain_err(err: twitch_archiver::errors::Error) -> Error {
+    format_err!("{}", err.display_chain())
+}
 
-    match args.format {
+/// Result of importing a single log file
+enum Outcome {
+    Imported {
+        new: usize,
+        duplicates: usize,
+        warnings: usize,
+        anomalies: usize,
+        rejected: usize,
+        repaired: usize
+    },
+    AlreadyImported,
+    Failed(twitch_archiver::errors::Error)
+}
+
+/// Parses a log file and stores all of its messages
+fn parse_file(collector: &mut Collector, parser: &ChattyParser, path: &Path, hints: &FileHints,
+              context: &mut ParserContext) -> twitch_archiver::errors::Result<ParseReport> {
+    let report = parser.parse_with_context(collector, open_log(path)?, hints, context)?;
+    collector.finish()?;
+    Ok(report)
+}
+
+/// Findings of the parser across all imported files
+#[derive(Default)]
+struct Findings {
+    anomalies: Vec<AnomalyRecord>,
+    rejects: Vec<RejectRecord>,
+    line_counts: BTreeMap<&'static str, usize>
+}
+
+/// Imports a log file, leaving nothing behind in the collector if it cannot be imported completely
+fn import(collector: &mut Collector, parser: &ChattyParser, path: &Path, hints: &FileHints,
+          context: &mut ParserContext, findings: &mut Findings) -> twitch_archiver::errors::Result<Outcome> {
+    let source = LogSource::from_path(path)?;
+    if !collector.begin(&source, ChattyParser::name())? {
+        // The file is still read to learn where it ended, as the next one may continue it
+        let reread = open_log(path)
+            .and_then(|log| parser.parse_with_context(&mut NullCollector, log, hints, context));
+        if let Err(err) = reread {
+            warn!("{}: reading the imported file failed: {}", path.display(), err);
+        }
+        return Ok(Outcome::AlreadyImported);
+    }
+
+    let before = collector.stats();
+    let report = match parse_file(collector, parser, path, hints, context) {
+        Ok(report) => report,
+        Err(err) => {
+            if let Err(abort_err) = collector.abort() {
+                error!("Rolling back {} failed: {}", path.display(), abort_err.display_chain());
+            }
+            return Err(err);
+        }
+    };
+    let after = collector.stats();
+    for warning in &report.date_warnings {
+        warn!("{}: {}", path.display(), warning);
+    }
+    for anomaly in &report.anomalies {
+        if parser.options().anomaly_policy == AnomalyPolicy::Warn {
+            warn!("{}: {}", path.display(), anomaly);
+        }
+        findings.anomalies.push(AnomalyRecord::new(path, anomaly));
+    }
+    for rejected in &report.rejects {
+        warn!("{}: {}", path.display(), rejected);
+        findings.rejects.push(RejectRecord::new(path, rejected));
+    }
+    for repaired in &report.repairs {
+        warn!("{}: {}", path.display(), repaired);
+    }
+    for (name, count) in &report.line_counts {
+        *findings.line_counts.entry(*name).or_insert(0) += *count;
+    }
+
+    Ok(Outcome::Imported {
+        new: after.inserted - before.inserted,
+        duplicates: after.duplicates - before.duplicates,
+        warnings: report.date_warnings.len(),
+        anomalies: report.anomalies.len(),
+        rejected: report.rejects.len(),
+        repaired: report.repairs.len()
+    })
+}
+
+/// Imports all files one after another and prints a summary of the results.
+///
+/// A file that fails to import is reported but does not stop the remaining files from being
+/// imported. Files are imported in the order of the dates hinted by their names, and each file
+/// of a hinted channel continues where the previously imported file of that channel ended.
+/// Returns what the parser found in all imported files.
+fn import_all(collector: &mut Collector, parser: &ChattyParser, patterns: &HintPatterns,
+              log_files: &LogFiles) -> Findings {
+    let mut outcomes = Vec::new();
+    let mut findings = Findings::default();
+    let (mut imported, mut skipped, mut failed) = (0, log_files.skipped.len(), 0);
+    let mut files = log_files.files.iter()
+        .map(|path| (path, patterns.hints(path)))
+        .collect::<Vec<_>>();
+    files.sort_by_key(|&(_, ref hints)| hints.date);
+    let mut contexts: BTreeMap<String, ParserContext> = BTreeMap::new();
+
+    for (path, hints) in files {
+        eprintln!("Importing log file {}", path.display());
+        let mut context = match hints.channel {
+            Some(ref channel) => contexts.remove(channel).unwrap_or_default(),
+            None => ParserContext::default()
+        };
+        let outcome = import(collector, parser, path, &hints, &mut context, &mut findings)
+            .unwrap_or_else(|err| {
+                error!("{}", err.display_chain());
+                Outcome::Failed(err)
+            });
+        match outcome {
+            Outcome::Imported { .. } => imported += 1,
+            Outcome::AlreadyImported => skipped += 1,
+            Outcome::Failed(_) => failed += 1,
+        }
+        // A file which failed leaves the context where the file before it ended
+        if let Some(channel) = hints.channel {
+            contexts.insert(channel, context);
+        }
+        outcomes.push((path, outcome));
+    }
+
+    eprintln!("{} imported, {} skipped, {} failed", imported, skipped, failed);
+    for (path, outcome) in outcomes {
+        match outcome {
+            Outcome::Imported { new, duplicates, warnings, anomalies, rejected, repaired } =>
+                eprintln!("  imported  {} ({} new, {} duplicates, {} warnings, {} anomalies, \
+                           {} rejected, {} repaired)",
+                          path.display(), new, duplicates, warnings, anomalies, rejected, repaired),
+            Outcome::AlreadyImported =>
+                eprintln!("  skipped   {} (already imported)", path.display()),
+            Outcome::Failed(err) =>
+                eprintln!("  failed    {}: {}", path.display(), err),
+        }
+    }
+    for path in &log_files.skipped {
+        eprintln!("  skipped   {}", path.display());
+    }
+    let line_counts = findings.line_counts.iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>();
+    eprintln!("Lines: {}", line_counts.join(", "));
+    if !findings.rejects.is_empty() {
+        warn!("{} lines were rejected and skipped, write them to a file with --rejects",
+              findings.rejects.len());
+    }
+    findings
+}
+
+#[derive(Debug, StructOpt)]
+struct GapsArgs {
+    /// PostgreSQL database URL
+    #[structopt(long = "database", short = "db")]
+    db_url: String,
+    /// Output file, defaults to stdout
+    #[structopt(long = "out", parse(from_os_str))]
+    out: Option<PathBuf>,
+    /// Only report this channel
+    #[structopt(long = "channel")]
+    channel: Option<String>,
+    /// Leave out the covered intervals
+    #[structopt(long = "gaps-only")]
+    gaps_only: bool,
+    /// IANA zone times are reported in, defaults to UTC
+    #[structopt(long = "zone")]
+    zone: Option<Tz>,
+}
+
+#[derive(Debug, StructOpt)]
+struct RepairArgs {
+    /// How bytes that are not valid UTF-8 are read (replace, latin1, cp1252)
+    #[structopt(long = "fallback", default_value = "replace")]
+    fallback: Encoding,
+    /// File the repaired log is written to, defaults to stdout
+    #[structopt(long = "out", parse(from_os_str))]
+    out: Option<PathBuf>,
+    /// File the report of all changes is written to, defaults to stderr
+    #[structopt(long = "report", parse(from_os_str))]
+    report: Option<PathBuf>,
+    /// The log file to repair
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+}
+
+fn import_command(args: ImportArgs) -> Result<()> {
+    if args.files.is_empty() { bail!("No log files given") };
+    let log_files = find_log_files(&args.files).map_err(chain_err)?;
+    let parser = ChattyParser::with_options(ParserOptions {
+        date_inference: match args.dates {
+            Dates::Rollover => DateInference::Rollover,
+            Dates::Bounded => DateInference::Bounded,
+        },
+        anomaly_policy: match args.anomalies {
+            Anomalies::Fail => AnomalyPolicy::Fail,
+            Anomalies::Warn => AnomalyPolicy::Warn,
+            Anomalies::Accept => AnomalyPolicy::Accept,
+        },
+        mode: if args.lenient { ParseMode::Lenient } else { ParseMode::Strict },
+        fallback: args.fallback.fallback(),
+        zone: args.log_zone
+    });
+    let patterns = if args.hint_patterns.is_empty() {
+        HintPatterns::default()
+    } else {
+        HintPatterns::new(&args.hint_patterns).map_err(chain_err)?
+    };
+
+    let findings = match args.format {
         Output::Pg => {
-            let connection = PgConnection::establish(
+            let db_url = args.db_url.ok_or_else(|| format_err!("Database URL missing"))?;
+            let connection = PgConnection::establish(&db_url)?;
+            let strategy = match args.load {
+                Load::Insert => LoadStrategy::Insert,
+                Load::Copy => LoadStrategy::copy(&db_url).map_err(chain_err)?,
+            };
+            let mut collector = PgCollector::with_strategy(&connection, strategy);
+            if args.preload {
+                collector.preload().map_err(chain_err)?;
+            }
+            collector.set_cleared_window(Duration::minutes(args.cleared_window));
+            import_all(&mut collector, &parser, &patterns, &log_files)
+        },
+        Output::Sqlite => {
+            let connection = SqliteConnection::establish(
                 args.db_url
-                    .ok_or_else(|| format_err!("Database URL missing"))?
+                    .ok_or_else(|| format_err!("Database path missing"))?
                     .as_ref()
             )?;
-            let mut collector = PgCollector::new(&connection);
-            if let Err(err) = ChattyParser::parse(&mut collector, BufReader::new(file)) {
-                error!("{}", err.display_chain());
-            }
+            SqliteCollector::run_migrations(&connection).map_err(chain_err)?;
+            let mut collector = SqliteCollector::new(&connection);
+            import_all(&mut collector, &parser, &patterns, &log_files)
+        },
+        Output::Csv => {
+            let mut collector = CsvCollector::with_zone(open_output(args.out)?, args.zone)
+                .map_err(chain_err)?;
+            import_all(&mut collector, &parser, &patterns, &log_files)
         },
-        Output::Csv => {}
+        Output::Jsonl => {
+            let mut collector = JsonLinesCollector::with_zone(BufWriter::new(open_output(args.out)?),
+                                                              args.zone);
+            import_all(&mut collector, &parser, &patterns, &log_files)
+        }
+    };
+    if let Some(path) = args.anomaly_report {
+        write_csv(File::create(path)?, &findings.anomalies).map_err(chain_err)?;
+    }
+    if let Some(path) = args.rejects {
+        write_csv(File::create(path)?, &findings.rejects).map_err(chain_err)?;
+    }
+    Ok(())
+}
+
+fn subs_command(args: ReportArgs) -> Result<()> {
+    let connection = PgConnection::establish(&args.db_url)?;
+    let days = subscriptions_per_day(&connection, args.zone.as_ref()).map_err(chain_err)?;
+    write_csv(open_output(args.out)?, &days).map_err(chain_err)
+}
+
+fn gaps_command(args: GapsArgs) -> Result<()> {
+    let connection = PgConnection::establish(&args.db_url)?;
+    let mut spans = session_spans(&connection).map_err(chain_err)?;
+    if let Some(ref channel) = args.channel {
+        spans.retain(|span| &span.channel == channel);
+    }
+    let mut intervals = coverage(spans);
+    if args.gaps_only {
+        intervals.retain(|interval| !interval.covered);
+    }
+    if let Some(ref zone) = args.zone {
+        localize(&mut intervals, zone);
+    }
+    write_csv(open_output(args.out)?, &intervals).map_err(chain_err)
+}
+
+fn repair_command(args: RepairArgs) -> Result<()> {
+    let repaired = repair(open_log(&args.file).map_err(chain_err)?, args.fallback.fallback())
+        .map_err(chain_err)?;
+    repaired.write_lines(BufWriter::new(open_output(args.out)?)).map_err(chain_err)?;
+    let report: Box<Write> = match args.report {
+        Some(path) => Box::new(File::create(path)?),
+        None => Box::new(io::stderr()),
+    };
+    repaired.write_report(report).map_err(chain_err)?;
+    eprintln!("{} fixes in {}", repaired.fixes.len(), args.file.display());
+    Ok(())
+}
+
+main!(|args: Cli| {
+    match args {
+        Cli::Import(args) => import_command(args)?,
+        Cli::Subs(args) => subs_command(args)?,
+        Cli::Gaps(args) => gaps_command(args)?,
+        Cli::Repair(args) => repair_command(args)?,
     }
 });
@@ -0,0 +1,153 @@
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use collector::*;
+use csv::Writer;
+use errors::Result;
+use input::LogSource;
+use std::io::Write;
+
+static HEADER: [&str; 6] = ["channel", "nick", "sent_at", "kind", "flags", "message"];
+
+/// Writes every collected message as a row of a CSV file
+pub struct CsvCollector<W: Write> {
+    writer: Writer<W>,
+    zone: Option<Tz>,
+    written: usize
+}
+
+impl <W: Write> CsvCollector<W> {
+    /// Creates a new collector writing to `output`, starting with a header row
+    pub fn new(output: W) -> Result<CsvCollector<W>> {
+        CsvCollector::with_zone(output, None)
+    }
+
+    /// Creates a new collector writing times in `zone`, or with the offset they were logged with
+    pub fn with_zone(output: W, zone: Option<Tz>) -> Result<CsvCollector<W>> {
+        let mut writer = Writer::from_writer(output);
+        writer.write_record(&HEADER)?;
+        Ok(CsvCollector { writer, zone, written: 0 })
+    }
+}
+
+impl <W: Write> Collector for CsvCollector<W> {
+    #[allow(unused_variables)]
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn add_message(&mut self, raw_message: RawMessage) -> Result<()> {
+        let flags = raw_message.flags.iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let sent_at = in_zone(raw_message.sent_at, self.zone.as_ref());
+
+        self.writer.write_record(&[
+            raw_message.channel,
+            raw_message.nick,
+            sent_at.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+            raw_message.kind.to_string(),
+            flags,
+            raw_message.message
+        ])?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Only messages are written, events are ignored
+    #[allow(unused_variables)]
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, moderation actions are ignored
+    #[allow(unused_variables)]
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, subscriptions are ignored
+    #[allow(unused_variables)]
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, sessions are ignored
+    #[allow(unused_variables)]
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.commit()
+    }
+
+    /// Rows which have already been written cannot be taken back
+    fn abort(&mut self) -> Result<()> {
+        self.commit()
+    }
+
+    fn stats(&self) -> CollectorStats {
+        CollectorStats { inserted: self.written, duplicates: 0 }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+    use models::types::{MessageFlag, MessageKind};
+
+    #[test]
+    fn write_rows() {
+        let mut output = Vec::new();
+        {
+            let mut collector = CsvCollector::new(&mut output).unwrap();
+            collector.add_message(RawMessage {
+                nick: "JohnDoe".to_owned(),
+                channel: "#_cerebot".to_owned(),
+                message: "hello, \"world\"".to_owned(),
+                sent_at: FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(0, 10, 0),
+                flags: vec!(MessageFlag::Moderator, MessageFlag::Prime),
+                kind: MessageKind::Action
+            }).unwrap();
+            collector.commit().unwrap();
+        }
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "channel,nick,sent_at,kind,flags,message\n\
+                    #_cerebot,JohnDoe,2017-10-06 00:10:00 +02:00,action,moderator prime,\"hello, \"\"world\"\"\"\n");
+    }
+
+    #[test]
+    fn write_rows_in_zone() {
+        let mut output = Vec::new();
+        {
+            let zone = "America/New_York".parse::<Tz>().unwrap();
+            let mut collector = CsvCollector::with_zone(&mut output, Some(zone)).unwrap();
+            collector.add_message(RawMessage {
+                nick: "JohnDoe".to_owned(),
+                channel: "#_cerebot".to_owned(),
+                message: "hello".to_owned(),
+                sent_at: FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(0, 10, 0),
+                flags: vec!(),
+                kind: MessageKind::Chat
+            }).unwrap();
+            collector.commit().unwrap();
+        }
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "channel,nick,sent_at,kind,flags,message\n\
+                    #_cerebot,JohnDoe,2017-10-05 18:10:00 -04:00,chat,,hello\n");
+    }
+}
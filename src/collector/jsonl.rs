@@ -0,0 +1,115 @@
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use collector::*;
+use errors::Result;
+use input::LogSource;
+use serde_json;
+use std::io::Write;
+
+/// Writes every collected message as a JSON object on its own line
+pub struct JsonLinesCollector<W: Write> {
+    output: W,
+    zone: Option<Tz>,
+    written: usize
+}
+
+impl <W: Write> JsonLinesCollector<W> {
+    pub fn new(output: W) -> JsonLinesCollector<W> {
+        JsonLinesCollector::with_zone(output, None)
+    }
+
+    /// Creates a new collector writing times in `zone`, or with the offset they were logged with
+    pub fn with_zone(output: W, zone: Option<Tz>) -> JsonLinesCollector<W> {
+        JsonLinesCollector { output, zone, written: 0 }
+    }
+}
+
+impl <W: Write> Collector for JsonLinesCollector<W> {
+    #[allow(unused_variables)]
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn add_message(&mut self, mut raw_message: RawMessage) -> Result<()> {
+        raw_message.sent_at = in_zone(raw_message.sent_at, self.zone.as_ref());
+        serde_json::to_writer(&mut self.output, &raw_message)?;
+        self.output.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Only messages are written, events are ignored
+    #[allow(unused_variables)]
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, moderation actions are ignored
+    #[allow(unused_variables)]
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, subscriptions are ignored
+    #[allow(unused_variables)]
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        Ok(())
+    }
+
+    /// Only messages are written, sessions are ignored
+    #[allow(unused_variables)]
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.output.flush()?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.commit()
+    }
+
+    /// Rows which have already been written cannot be taken back
+    fn abort(&mut self) -> Result<()> {
+        self.commit()
+    }
+
+    fn stats(&self) -> CollectorStats {
+        CollectorStats { inserted: self.written, duplicates: 0 }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+    use models::types::{MessageFlag, MessageKind};
+
+    #[test]
+    fn write_lines() {
+        let mut output = Vec::new();
+        {
+            let mut collector = JsonLinesCollector::new(&mut output);
+            collector.add_message(RawMessage {
+                nick: "JohnDoe".to_owned(),
+                channel: "#_cerebot".to_owned(),
+                message: "test message".to_owned(),
+                sent_at: FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(0, 10, 0),
+                flags: vec!(MessageFlag::Subscriber),
+                kind: MessageKind::Chat
+            }).unwrap();
+            collector.commit().unwrap();
+        }
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "{\"nick\":\"JohnDoe\",\"channel\":\"#_cerebot\",\"message\":\"test message\",\
+                    \"sent_at\":\"2017-10-06T00:10:00+02:00\",\"flags\":[\"subscriber\"],\"kind\":\"chat\"}\n");
+    }
+}
@@ -1,48 +1,257 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset};
+use chrono_tz::Tz;
 use errors::Result;
-use models::types::MessageFlag;
-pub use self::pg::PgCollector;
+use input::LogSource;
+use models::types::{MessageFlag, MessageKind, ModerationKind, SubscriptionKind, SubscriptionTier};
+pub use self::csv::CsvCollector;
+pub use self::jsonl::JsonLinesCollector;
+pub use self::pg::{LoadStrategy, PgCollector};
+pub use self::sqlite::SqliteCollector;
 
+mod csv;
+mod jsonl;
 mod pg;
+mod sqlite;
 
 static DEFAULT_BATCH_SIZE: usize = 3000;
 
 pub trait Collector {
+    /// Called before the messages of a log file are added.
+    ///
+    /// Returns `false` if the file has already been imported and should be skipped.
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool>;
     fn add_message(&mut self, raw_message: RawMessage) -> Result<()>;
+    /// Adds a system line of the log which is not a chat message
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()>;
+    /// Adds a timeout, ban or the lifting of one
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()>;
+    /// Adds a subscription, resubscription or gifted sub notice
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()>;
+    /// Called when the log of a new client session starts, messages added afterwards belong to it
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()>;
+    /// Called when the current session is over, also if the log was never closed
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()>;
     fn commit(&mut self) -> Result<()>;
+    /// Stores any remaining messages and completes the import of the current log file
+    fn finish(&mut self) -> Result<()>;
+    /// Discards the current log file, undoing everything stored for it where possible
+    fn abort(&mut self) -> Result<()>;
+    fn stats(&self) -> CollectorStats;
+}
+
+/// Number of messages a collector has stored so far
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CollectorStats {
+    /// Messages which were newly stored
+    pub inserted: usize,
+    /// Messages which were skipped because they had already been stored before
+    pub duplicates: usize
 }
 
 #[derive(Default)]
 pub struct VecCollector {
-    pub messages: Vec<RawMessage>
+    pub messages: Vec<RawMessage>,
+    pub events: Vec<RawEvent>,
+    pub moderation_actions: Vec<RawModerationAction>,
+    pub subscriptions: Vec<RawSubscription>,
+    pub sessions: Vec<RawSession>
 }
 
 impl VecCollector {
     pub fn new() -> VecCollector {
         VecCollector {
-            messages: vec!()
+            messages: vec!(),
+            events: vec!(),
+            moderation_actions: vec!(),
+            subscriptions: vec!(),
+            sessions: vec!()
         }
     }
 }
 
 impl Collector for VecCollector {
+    #[allow(unused_variables)]
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        Ok(true)
+    }
+
     #[allow(unused_variables)]
     fn add_message(&mut self, raw_message: RawMessage) -> Result<()> {
         self.messages.push(raw_message);
         Ok(())
     }
 
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        self.events.push(raw_event);
+        Ok(())
+    }
+
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        self.moderation_actions.push(raw_action);
+        Ok(())
+    }
+
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        self.subscriptions.push(raw_subscription);
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        self.sessions.push(raw_session);
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn commit(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn abort(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> CollectorStats {
+        CollectorStats { inserted: self.messages.len(), duplicates: 0 }
+    }
+}
+
+/// Discards everything, for reading a log only to learn where it ends
+pub struct NullCollector;
+
+impl Collector for NullCollector {
+    #[allow(unused_variables)]
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    #[allow(unused_variables)]
+    fn add_message(&mut self, raw_message: RawMessage) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        Ok(())
+    }
+
     #[allow(unused_variables)]
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        Ok(())
+    }
+
     fn commit(&mut self) -> Result<()> {
         Ok(())
     }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn abort(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn stats(&self) -> CollectorStats {
+        CollectorStats::default()
+    }
 }
 
-#[derive(Debug,Clone)]
+/// Converts a time into the given zone, or keeps its original offset if no zone is given
+pub fn in_zone(time: DateTime<FixedOffset>, zone: Option<&Tz>) -> DateTime<FixedOffset> {
+    match zone {
+        Some(zone) => {
+            let local = time.with_timezone(zone);
+            local.with_timezone(&local.offset().fix())
+        },
+        None => time
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RawMessage {
     pub nick: String,
     pub channel: String,
     pub message: String,
+    /// Time with the offset the message was logged with
+    pub sent_at: DateTime<FixedOffset>,
+    pub flags: Vec<MessageFlag>,
+    pub kind: MessageKind
+}
+
+/// A system line of the log, such as a ban, host or subscription notice
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawEvent {
+    /// Channel the event was logged in, if the log had joined one yet
+    pub channel: Option<String>,
     pub sent_at: NaiveDateTime,
-    pub flags: Vec<MessageFlag>
+    pub message: String
+}
+
+/// A timeout or ban of a user, or the lifting of one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawModerationAction {
+    pub channel: String,
+    pub sent_at: NaiveDateTime,
+    pub kind: ModerationKind,
+    /// Nick of the user the action was taken against
+    pub target: String,
+    /// Length of a timeout in seconds
+    pub duration: Option<u32>,
+    pub reason: Option<String>,
+    /// Nick of the moderator, only logged if the logging account could see moderator actions
+    pub moderator: Option<String>
+}
+
+/// A subscription notice
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawSubscription {
+    pub channel: String,
+    pub sent_at: NaiveDateTime,
+    pub kind: SubscriptionKind,
+    pub tier: SubscriptionTier,
+    /// Nick of the subscribing user, or of the gifter of a gifted sub
+    pub subscriber: String,
+    /// Nick of the receiver of a gifted sub
+    pub recipient: Option<String>,
+    /// Number of months subscribed in total
+    pub months: u32,
+    /// Message the subscriber attached to the notice
+    pub message: Option<String>
+}
+
+/// A run of the logging client, from `Log started` to `Log closed`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawSession {
+    /// Channel joined during the session
+    pub channel: Option<String>,
+    pub started_at: NaiveDateTime,
+    /// Missing if the client stopped without closing the log, e.g. because it crashed
+    pub ended_at: Option<NaiveDateTime>,
+    /// Time of the last line logged in the session
+    pub last_seen_at: NaiveDateTime,
+    pub message_count: usize
 }
@@ -1,110 +1,853 @@
+use chrono::{DateTime, Duration, FixedOffset, NaiveDateTime, Utc};
 use collector::*;
 use diesel;
+use diesel::connection::TransactionManager;
 use diesel::prelude::*;
 use diesel::pg::PgConnection;
+use diesel::pg::upsert::*;
+use diesel::types::VarChar;
 use errors::Result;
-use models::{Channel, NewChannel, NewMessage, NewUser, User};
-use schema::{channels, messages, users};
+use input::LogSource;
+use models::{Channel, Import, ModerationAction, NewChannel, NewEvent, NewImport, NewMessage,
+             NewModerationAction, NewSession, NewSubscription, NewUser, Session, User};
+use models::types::ModerationKind;
+use postgres;
+use schema::{channels, events, imports, messages, moderation_actions, sessions, subscriptions, users};
 use std::collections::HashMap;
+use std::io::Write;
+
+static COPY_COLUMNS: &str =
+    "user_id, channel_id, message, sent_at, flags, occurrence, import_id, kind, session_id, \
+     utc_offset";
+/// How far back messages are cleared from chat when their sender is timed out or banned, unless
+/// set with `set_cleared_window`
+const DEFAULT_CLEARED_MINUTES: i64 = 10;
+
+sql_function!(lower, lower_t, (x: VarChar) -> VarChar);
+
+/// How message batches are written to the database
+pub enum LoadStrategy {
+    /// Multi-row INSERT statements through the diesel connection
+    Insert,
+    /// COPY FROM STDIN through a second connection to the same database
+    Copy(CopyLoader)
+}
+
+impl LoadStrategy {
+    /// Opens the second connection used for loading with COPY
+    pub fn copy(database_url: &str) -> Result<LoadStrategy> {
+        Ok(LoadStrategy::Copy(CopyLoader::connect(database_url)?))
+    }
+}
+
+/// Loads message batches into the unlogged `messages_staging` table using COPY.
+///
+/// Staged rows are tagged with the backend id of the loading connection and then moved into
+/// `messages` by the main connection. That way they become part of the import transaction, and
+/// messages which are already archived are still skipped.
+pub struct CopyLoader {
+    client: postgres::Client,
+    token: i32
+}
+
+impl CopyLoader {
+    fn connect(database_url: &str) -> Result<CopyLoader> {
+        let client = postgres::Client::connect(database_url, postgres::NoTls)?;
+        let mut loader = CopyLoader { token: 0, client };
+        loader.token = loader.client.query_one("SELECT pg_backend_pid()", &[])?.get(0);
+        // Backend ids are reused, so rows left behind by a crashed loader with the same id have to
+        // go before they could be taken for ours
+        loader.clear()?;
+        Ok(loader)
+    }
+
+    /// Copies a batch of messages into the staging table
+    fn copy(&mut self, batch: &[NewMessage]) -> Result<()> {
+        let mut data = Vec::new();
+        for message in batch {
+            write!(data, "{}\t", self.token)?;
+            write_copy_row(&mut data, message)?;
+        }
+        let mut writer = self.client.copy_in(
+            format!("COPY messages_staging (token, {}) FROM STDIN", COPY_COLUMNS).as_str()
+        )?;
+        writer.write_all(&data)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Moves the staged rows into `messages` and returns how many of them were new
+    fn move_into_messages(&self, connection: &PgConnection) -> Result<usize> {
+        let inserted = connection.execute(&format!(
+            "INSERT INTO messages ({0}) SELECT {0} FROM messages_staging WHERE token = {1} \
+             ON CONFLICT DO NOTHING",
+            COPY_COLUMNS, self.token
+        ))?;
+        connection.execute(&format!("DELETE FROM messages_staging WHERE token = {}", self.token))?;
+        Ok(inserted)
+    }
+
+    /// Removes staged rows whose move into `messages` was rolled back
+    fn clear(&mut self) -> Result<()> {
+        self.client.execute("DELETE FROM messages_staging WHERE token = $1", &[&self.token])?;
+        Ok(())
+    }
+}
 
 pub struct PgCollector<'a> {
     batch_size: usize,
-    message_batch: Vec<NewMessage>,
+    /// Messages waiting to be stored, together with their occurrence index and session
+    pending: Vec<(RawMessage, i16, Option<i32>)>,
+    pending_events: Vec<RawEvent>,
+    pending_actions: Vec<RawModerationAction>,
+    pending_subscriptions: Vec<RawSubscription>,
     channel_map: HashMap<String, i32>,
     user_map: HashMap<String, i32>,
+    /// Names of the channels and users created by the current import, forgotten if it is aborted
+    created_channels: Vec<String>,
+    created_users: Vec<String>,
+    last_sent_at: Option<DateTime<FixedOffset>>,
+    occurrences: HashMap<(String, String, String), i16>,
+    stats: CollectorStats,
+    import_id: Option<i32>,
+    /// Path of the file being imported, which together with their start identifies sessions
+    source_path: Option<String>,
+    session_id: Option<i32>,
+    import_start: CollectorStats,
+    in_transaction: bool,
+    cleared_window: Duration,
+    strategy: LoadStrategy,
     connection: &'a PgConnection
 }
 
 impl <'a> Collector for PgCollector<'a> {
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        let previous_imports: i64 = imports::table
+            .filter(imports::content_hash.eq(source.content_hash.as_str()))
+            .filter(imports::finished_at.is_not_null())
+            .count()
+            .get_result(self.connection)?;
+        if previous_imports > 0 {
+            return Ok(false);
+        }
+
+        self.connection.transaction_manager().begin_transaction(self.connection)?;
+        self.in_transaction = true;
+        self.import_id = Some(self.create_import(source, parser)?);
+        self.source_path = Some(source.path.to_string_lossy().into_owned());
+        self.import_start = self.stats;
+        self.last_sent_at = None;
+        self.occurrences.clear();
+        self.created_channels.clear();
+        self.created_users.clear();
+        Ok(true)
+    }
+
     fn add_message(&mut self, raw_message: RawMessage) -> Result<()> {
-        let new_user_id: i32 = self.find_or_create_user_id(raw_message.nick.as_ref())?;
-        let new_channel_id: i32 = self.find_or_create_channel_id(raw_message.channel.as_ref())?;
-
-        let new_message = NewMessage {
-            user_id: new_user_id,
-            channel_id: new_channel_id,
-            message: raw_message.message,
-            sent_at: raw_message.sent_at,
-            flags: raw_message.flags.into_iter().map(|f| f.into()).collect()
-        };
+        let occurrence = self.next_occurrence(&raw_message);
+        self.pending.push((raw_message, occurrence, self.session_id));
+
+        if self.pending.len() >= self.batch_size {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
 
-        self.message_batch.push(new_message);
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        self.pending_events.push(raw_event);
 
-        if self.message_batch.len() >= self.batch_size {
+        if self.pending_events.len() >= self.batch_size {
             self.commit()?;
         }
 
         Ok(())
     }
 
-    fn commit(&mut self) -> Result<()> {
-        diesel::insert(&self.message_batch).into(messages::table)
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        self.pending_actions.push(raw_action);
+
+        if self.pending_actions.len() >= self.batch_size {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        self.pending_subscriptions.push(raw_subscription);
+
+        if self.pending_subscriptions.len() >= self.batch_size {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        let new_session = NewSession {
+            started_at,
+            import_id: self.import_id,
+            source_path: self.source_path.as_ref().map(|path| path.as_str())
+        };
+        // A session of a file which was imported before is reused, so messages link to one session
+        let session: Session = diesel::insert(&new_session.on_conflict(
+                (sessions::source_path, sessions::started_at),
+                do_update().set(sessions::started_at.eq(started_at))
+            ))
+            .into(sessions::table)
+            .get_result(self.connection)?;
+        self.session_id = Some(session.id);
+        Ok(())
+    }
+
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        let session_id = match self.session_id.take() {
+            Some(session_id) => session_id,
+            None => return Ok(())
+        };
+        let channel_id = match raw_session.channel {
+            Some(ref channel) => {
+                resolve_channel_ids(self.connection, &mut self.channel_map, vec!(channel.as_str()))?;
+                Some(self.channel_map[channel])
+            },
+            None => None
+        };
+        diesel::update(sessions::table.find(session_id))
+            .set((
+                sessions::channel_id.eq(channel_id),
+                sessions::ended_at.eq(raw_session.ended_at),
+                sessions::last_seen_at.eq(raw_session.last_seen_at),
+                sessions::message_count.eq(raw_session.message_count as i32)
+            ))
             .execute(self.connection)?;
-        self.message_batch.clear();
         Ok(())
     }
+
+    fn commit(&mut self) -> Result<()> {
+        if self.pending.is_empty() && self.pending_events.is_empty() && self.pending_actions.is_empty()
+            && self.pending_subscriptions.is_empty() {
+            return Ok(());
+        }
+        self.resolve_ids()?;
+        self.store_events()?;
+        self.store_messages()?;
+        self.store_moderation_actions()?;
+        self.store_subscriptions()
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.commit()?;
+        if let Some(import_id) = self.import_id.take() {
+            self.finish_import(import_id)?;
+        }
+        self.source_path = None;
+        if self.in_transaction {
+            self.in_transaction = false;
+            self.connection.transaction_manager().commit_transaction(self.connection)?;
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self) -> Result<()> {
+        self.pending.clear();
+        self.pending_events.clear();
+        self.pending_actions.clear();
+        self.pending_subscriptions.clear();
+        self.import_id = None;
+        self.source_path = None;
+        self.session_id = None;
+        self.stats = self.import_start;
+        if self.in_transaction {
+            self.in_transaction = false;
+            // Ids of users and channels created by this file are invalid after the rollback
+            for name in self.created_users.drain(..) {
+                self.user_map.remove(&name);
+            }
+            for name in self.created_channels.drain(..) {
+                self.channel_map.remove(&name);
+            }
+            self.connection.transaction_manager().rollback_transaction(self.connection)?;
+            if let LoadStrategy::Copy(ref mut loader) = self.strategy {
+                loader.clear()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> CollectorStats {
+        self.stats
+    }
 }
 
 impl <'a> PgCollector<'a> {
+    /// Creates a collector storing messages in the given database.
+    ///
+    /// Each log file is imported in its own transaction, which is only committed by `finish`.
+    /// Messages added outside of `begin` and `finish` are stored by `commit`.
     pub fn new(connection: &PgConnection) -> PgCollector {
+        PgCollector::with_strategy(connection, LoadStrategy::Insert)
+    }
+
+    /// Creates a collector which writes messages using the given loading strategy
+    pub fn with_strategy(connection: &PgConnection, strategy: LoadStrategy) -> PgCollector {
         PgCollector {
             batch_size: DEFAULT_BATCH_SIZE,
-            message_batch: Vec::new(),
+            pending: Vec::new(),
+            pending_events: Vec::new(),
+            pending_actions: Vec::new(),
+            pending_subscriptions: Vec::new(),
             channel_map: HashMap::new(),
             user_map: HashMap::new(),
+            created_channels: Vec::new(),
+            created_users: Vec::new(),
+            last_sent_at: None,
+            occurrences: HashMap::new(),
+            stats: CollectorStats::default(),
+            import_id: None,
+            source_path: None,
+            session_id: None,
+            import_start: CollectorStats::default(),
+            in_transaction: false,
+            cleared_window: Duration::minutes(DEFAULT_CLEARED_MINUTES),
+            strategy,
             connection,
         }
     }
 
-    fn find_user_by_nick(&self, nick: &str) -> Option<User> {
-        use self::users::dsl::*;
-        let user = users.filter(name.eq(nick))
-            .first(self.connection);
+    /// Records a new import of the given file and returns its id
+    fn create_import(&self, source: &LogSource, parser: &str) -> Result<i32> {
+        let source_path = source.path.to_string_lossy();
+        let new_import = NewImport {
+            source_path: source_path.as_ref(),
+            content_hash: source.content_hash.as_ref(),
+            byte_size: source.byte_size as i64,
+            started_at: Utc::now().naive_utc(),
+            parser
+        };
+        let import: Import = diesel::insert(&new_import).into(imports::table)
+            .get_result(self.connection)?;
+        Ok(import.id)
+    }
 
-        user.ok()
+    /// Stores the completion time and message counts of an import
+    fn finish_import(&self, import_id: i32) -> Result<()> {
+        let inserted = (self.stats.inserted - self.import_start.inserted) as i32;
+        let duplicates = (self.stats.duplicates - self.import_start.duplicates) as i32;
+        diesel::update(imports::table.find(import_id))
+            .set((
+                imports::finished_at.eq(Utc::now().naive_utc()),
+                imports::message_count.eq(inserted),
+                imports::duplicate_count.eq(duplicates)
+            ))
+            .execute(self.connection)?;
+        Ok(())
     }
 
-    fn find_channel_by_name(&self, channel: &str) -> Option<Channel> {
-        use self::channels::dsl::*;
-        let channel = channels.filter(name.eq(channel))
-            .first(self.connection);
+    /// Counts how often the same user has sent the same text in a channel within the same second.
+    ///
+    /// Together with channel, user, time and text this forms the natural key of a message, which
+    /// keeps repeated messages apart while letting re-imports of the same log be detected.
+    fn next_occurrence(&mut self, raw_message: &RawMessage) -> i16 {
+        if self.last_sent_at != Some(raw_message.sent_at) {
+            self.occurrences.clear();
+            self.last_sent_at = Some(raw_message.sent_at);
+        }
+        let key = (raw_message.channel.clone(), raw_message.nick.clone(), raw_message.message.clone());
+        let count = self.occurrences
+            .entry(key)
+            .or_insert(0);
+        *count += 1;
+        *count - 1
+    }
 
-        channel.ok()
+    /// Loads the ids of all existing users and channels, so only new ones have to be looked up
+    pub fn preload(&mut self) -> Result<()> {
+        let all_users: Vec<User> = users::table.load(self.connection)?;
+        self.user_map.extend(all_users.into_iter().map(|u| (u.name, u.id)));
+        let all_channels: Vec<Channel> = channels::table.load(self.connection)?;
+        self.channel_map.extend(all_channels.into_iter().map(|c| (c.name, c.id)));
+        Ok(())
     }
 
-    fn find_or_create_channel_id(&self, name: &str) -> Result<i32> {
-        if let Some(id) = self.channel_map.get(name) {
-            Ok(*id)
-        } else if let Some(channel) = self.find_channel_by_name(name) {
-            Ok(channel.id)
-        } else {
-            let new_channel = NewChannel { name };
-            diesel::insert(&new_channel).into(channels::table)
-                .get_result(self.connection)
-                .map(|c: Channel| c.id)
-                .map_err(|e| e.into())
+    /// Makes sure the ids of all users and channels of the pending records are cached
+    fn resolve_ids(&mut self) -> Result<()> {
+        let nicks = self.pending.iter()
+            .map(|&(ref m, _, _)| m.nick.as_str())
+            .chain(self.pending_actions.iter().map(|a| a.target.as_str()))
+            .chain(self.pending_actions.iter().filter_map(|a| a.moderator.as_ref().map(|m| m.as_str())))
+            .chain(self.pending_subscriptions.iter().map(|s| s.subscriber.as_str()))
+            .chain(self.pending_subscriptions.iter().filter_map(|s| s.recipient.as_ref().map(|r| r.as_str())))
+            .collect();
+        let channel_names = self.pending.iter()
+            .map(|&(ref m, _, _)| m.channel.as_str())
+            .chain(self.pending_events.iter().filter_map(|e| e.channel.as_ref().map(|c| c.as_str())))
+            .chain(self.pending_actions.iter().map(|a| a.channel.as_str()))
+            .chain(self.pending_subscriptions.iter().map(|s| s.channel.as_str()))
+            .collect();
+
+        let created_users = resolve_user_ids(self.connection, &mut self.user_map, nicks)?;
+        let created_channels = resolve_channel_ids(self.connection, &mut self.channel_map, channel_names)?;
+        if self.in_transaction {
+            self.created_users.extend(created_users);
+            self.created_channels.extend(created_channels);
         }
+        Ok(())
     }
 
-    fn find_or_create_user_id(&self, name: &str) -> Result<i32> {
-        if let Some(id) = self.user_map.get(name) {
-            Ok(*id)
-        } else if let Some(user) = self.find_user_by_nick(name) {
-            Ok(user.id)
-        } else {
-            let new_user = NewUser {
-                name,
-            };
-            diesel::insert(&new_user).into(users::table)
-                .get_result(self.connection)
-                .map(|u: User| u.id)
-                .map_err(|e| e.into())
+    fn store_messages(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
         }
+
+        let message_batch: Vec<NewMessage> = {
+            let user_map = &self.user_map;
+            let channel_map = &self.channel_map;
+            let import_id = self.import_id;
+            self.pending.drain(..)
+                .map(|(raw_message, occurrence, session_id)| NewMessage {
+                    user_id: user_map[&raw_message.nick],
+                    channel_id: channel_map[&raw_message.channel],
+                    message: raw_message.message,
+                    sent_at: raw_message.sent_at.with_timezone(&Utc),
+                    flags: raw_message.flags.into_iter().map(|f| f.into()).collect(),
+                    occurrence,
+                    import_id,
+                    kind: raw_message.kind.into(),
+                    session_id,
+                    utc_offset: raw_message.sent_at.offset().local_minus_utc()
+                })
+                .collect()
+        };
+
+        let inserted = match self.strategy {
+            LoadStrategy::Insert => {
+                diesel::insert(&message_batch.on_conflict_do_nothing())
+                    .into(messages::table)
+                    .execute(self.connection)?
+            },
+            LoadStrategy::Copy(ref mut loader) => {
+                loader.copy(&message_batch)?;
+                loader.move_into_messages(self.connection)?
+            }
+        };
+        self.stats.inserted += inserted;
+        self.stats.duplicates += message_batch.len() - inserted;
+        Ok(())
+    }
+
+    /// Stores the pending events, skipping those which are already archived
+    fn store_events(&mut self) -> Result<()> {
+        if self.pending_events.is_empty() {
+            return Ok(());
+        }
+
+        let new_events: Vec<NewEvent> = {
+            let channel_map = &self.channel_map;
+            let import_id = self.import_id;
+            self.pending_events.drain(..)
+                .map(|raw_event| NewEvent {
+                    channel_id: raw_event.channel.map(|channel| channel_map[&channel]),
+                    sent_at: raw_event.sent_at,
+                    message: raw_event.message,
+                    import_id
+                })
+                .collect()
+        };
+        diesel::insert(&new_events.on_conflict_do_nothing()).into(events::table)
+            .execute(self.connection)?;
+        Ok(())
+    }
+
+    /// Stores the pending moderation actions, skipping those which are already archived
+    fn store_moderation_actions(&mut self) -> Result<()> {
+        if self.pending_actions.is_empty() {
+            return Ok(());
+        }
+
+        let target_names: HashMap<i32, String> = self.pending_actions.iter()
+            .map(|raw_action| (self.user_map[&raw_action.target], raw_action.target.to_lowercase()))
+            .collect();
+        let new_actions: Vec<NewModerationAction> = {
+            let user_map = &self.user_map;
+            let channel_map = &self.channel_map;
+            let import_id = self.import_id;
+            self.pending_actions.drain(..)
+                .map(|raw_action| NewModerationAction {
+                    channel_id: channel_map[&raw_action.channel],
+                    target_id: user_map[&raw_action.target],
+                    moderator_id: raw_action.moderator.map(|moderator| user_map[&moderator]),
+                    kind: raw_action.kind.into(),
+                    duration: raw_action.duration.map(|d| d as i32),
+                    reason: raw_action.reason,
+                    sent_at: raw_action.sent_at,
+                    import_id
+                })
+                .collect()
+        };
+        let stored: Vec<ModerationAction> = diesel::insert(&new_actions.on_conflict_do_nothing())
+            .into(moderation_actions::table)
+            .get_results(self.connection)?;
+        let clearing: Vec<ModerationAction> = stored.into_iter()
+            .filter(|action| action.kind == ModerationKind::Timeout || action.kind == ModerationKind::Ban)
+            .collect();
+        if clearing.is_empty() {
+            return Ok(());
+        }
+
+        // Moderation lines log the login name while messages carry the display name, which may
+        // differ in case, so the users behind all targets are matched case-insensitively at once
+        let mut names: Vec<&String> = target_names.values().collect();
+        names.sort();
+        names.dedup();
+        let matches: Vec<(i32, String)> = users::table
+            .select((users::id, lower(users::name)))
+            .filter(lower(users::name).eq_any(names))
+            .load(self.connection)?;
+        let mut user_ids: HashMap<String, Vec<i32>> = HashMap::new();
+        for (id, name) in matches {
+            user_ids.entry(name).or_insert_with(Vec::new).push(id);
+        }
+
+        for action in &clearing {
+            let target_ids = &user_ids[&target_names[&action.target_id]];
+            self.mark_deleted(action, target_ids)?;
+        }
+        Ok(())
+    }
+
+    /// Sets how far back a timeout or ban clears the messages of its target, 10 minutes by default
+    pub fn set_cleared_window(&mut self, window: Duration) {
+        self.cleared_window = window;
+    }
+
+    /// Marks the recent messages of the target of a timeout or ban, given as the ids of all users
+    /// with its name, as deleted by it
+    fn mark_deleted(&self, action: &ModerationAction, target_ids: &[i32]) -> Result<()> {
+        let cleared_until = DateTime::<Utc>::from_utc(action.sent_at, Utc);
+        let cleared_since = cleared_until - self.cleared_window;
+        diesel::update(messages::table
+            .filter(messages::channel_id.eq(action.channel_id))
+            .filter(messages::user_id.eq_any(target_ids.to_vec()))
+            .filter(messages::sent_at.ge(cleared_since))
+            .filter(messages::sent_at.le(cleared_until))
+            .filter(messages::deleted_at.is_null()))
+            .set((
+                messages::deleted_at.eq(cleared_until),
+                messages::deleted_by_action.eq(action.id)
+            ))
+            .execute(self.connection)?;
+        Ok(())
+    }
+
+    /// Stores the pending subscriptions, skipping those which are already archived
+    fn store_subscriptions(&mut self) -> Result<()> {
+        if self.pending_subscriptions.is_empty() {
+            return Ok(());
+        }
+
+        let new_subscriptions: Vec<NewSubscription> = {
+            let user_map = &self.user_map;
+            let channel_map = &self.channel_map;
+            let import_id = self.import_id;
+            self.pending_subscriptions.drain(..)
+                .map(|raw_subscription| NewSubscription {
+                    channel_id: channel_map[&raw_subscription.channel],
+                    subscriber_id: user_map[&raw_subscription.subscriber],
+                    recipient_id: raw_subscription.recipient.map(|recipient| user_map[&recipient]),
+                    kind: raw_subscription.kind.into(),
+                    tier: raw_subscription.tier.into(),
+                    months: raw_subscription.months as i32,
+                    message: raw_subscription.message,
+                    sent_at: raw_subscription.sent_at,
+                    import_id
+                })
+                .collect()
+        };
+        diesel::insert(&new_subscriptions.on_conflict_do_nothing()).into(subscriptions::table)
+            .execute(self.connection)?;
+        Ok(())
     }
 }
 
+/// Adds the ids of the given nicks to the cache and returns the nicks of the users it created.
+///
+/// Nicks which are not cached yet are created in one statement, skipping those which already
+/// exist, and their ids are then fetched in one query.
+fn resolve_user_ids(connection: &PgConnection, user_map: &mut HashMap<String, i32>,
+                    mut nicks: Vec<&str>) -> Result<Vec<String>> {
+    nicks.retain(|nick| !user_map.contains_key(*nick));
+    if nicks.is_empty() {
+        return Ok(Vec::new());
+    }
+    nicks.sort();
+    nicks.dedup();
+
+    let new_users: Vec<NewUser> = nicks.iter().map(|&name| NewUser { name }).collect();
+    let created: Vec<User> = diesel::insert(&new_users.on_conflict_do_nothing()).into(users::table)
+        .get_results(connection)?;
+    let ids: Vec<(i32, String)> = users::table
+        .select((users::id, users::name))
+        .filter(users::name.eq_any(nicks))
+        .load(connection)?;
+    user_map.extend(ids.into_iter().map(|(id, name)| (name, id)));
+    Ok(created.into_iter().map(|u| u.name).collect())
+}
+
+/// Adds the ids of the given channels to the cache, creating the missing ones, and returns the
+/// names of the channels it created
+fn resolve_channel_ids(connection: &PgConnection, channel_map: &mut HashMap<String, i32>,
+                       mut names: Vec<&str>) -> Result<Vec<String>> {
+    names.retain(|name| !channel_map.contains_key(*name));
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+    names.sort();
+    names.dedup();
+
+    let new_channels: Vec<NewChannel> = names.iter().map(|&name| NewChannel { name }).collect();
+    let created: Vec<Channel> = diesel::insert(&new_channels.on_conflict_do_nothing())
+        .into(channels::table)
+        .get_results(connection)?;
+    let ids: Vec<(i32, String)> = channels::table
+        .select((channels::id, channels::name))
+        .filter(channels::name.eq_any(names))
+        .load(connection)?;
+    channel_map.extend(ids.into_iter().map(|(id, name)| (name, id)));
+    Ok(created.into_iter().map(|c| c.name).collect())
+}
+
+/// Writes a message as a row in PostgreSQL's COPY text format
+fn write_copy_row<W: Write>(output: &mut W, message: &NewMessage) -> Result<()> {
+    let flags = message.flags.iter()
+        .map(|f| f.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let import_id = message.import_id
+        .map_or_else(|| "\\N".to_owned(), |id| id.to_string());
+    let session_id = message.session_id
+        .map_or_else(|| "\\N".to_owned(), |id| id.to_string());
+
+    writeln!(output, "{}\t{}\t{}\t{}\t{{{}}}\t{}\t{}\t{}\t{}\t{}",
+             message.user_id,
+             message.channel_id,
+             escape_copy_text(&message.message),
+             message.sent_at.format("%Y-%m-%d %H:%M:%S%.f%:z"),
+             flags,
+             message.occurrence,
+             import_id,
+             message.kind,
+             session_id,
+             message.utc_offset)?;
+    Ok(())
+}
+
+fn escape_copy_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A file whose import was neither finished nor aborted is rolled back, so an interrupted import
+/// leaves nothing behind. Records added outside of an import are stored like by `commit`.
 impl <'a> Drop for PgCollector<'a> {
     fn drop(&mut self) {
-        self.commit().unwrap();
+        if self.in_transaction {
+            if let Err(err) = self.abort() {
+                eprintln!("Rolling back unfinished import failed: {}", err);
+            }
+        } else if let Err(err) = self.commit() {
+            eprintln!("Storing pending records failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use diesel::expression::dsl::sql;
+    use diesel::types::BigInt;
+    use models::types::{MessageFlag, MessageKind};
+    use std::env;
+    use std::path::PathBuf;
+    use std::time::Instant;
+
+    /// Connects to the migrated database named by `TEST_DATABASE_URL`
+    fn test_database() -> (PgConnection, String) {
+        let url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL is not set");
+        (PgConnection::establish(&url).unwrap(), url)
+    }
+
+    /// Stores `count` messages in an import which is rolled back afterwards
+    fn load_messages(collector: &mut PgCollector, count: usize) -> CollectorStats {
+        let source = LogSource {
+            path: PathBuf::from("load_test.log"),
+            content_hash: format!("load test {}", Utc::now()),
+            byte_size: 0
+        };
+        assert!(collector.begin(&source, "test").unwrap());
+        let start = FixedOffset::east(2 * 3600).ymd(2017, 10, 5).and_hms(20, 0, 0);
+        // Messages sent in the same second with the same text are kept apart by their occurrence
+        collector.add_message(RawMessage {
+            nick: "user0".to_owned(),
+            channel: "#_load_test".to_owned(),
+            message: "test\tmessage 0".to_owned(),
+            sent_at: start,
+            flags: vec!(),
+            kind: MessageKind::Chat
+        }).unwrap();
+        for i in 0..count {
+            collector.add_message(RawMessage {
+                nick: format!("user{}", i % 100),
+                channel: "#_load_test".to_owned(),
+                message: format!("test\tmessage {}", i),
+                sent_at: start + Duration::seconds((i / 10) as i64),
+                flags: vec!(MessageFlag::Subscriber),
+                kind: MessageKind::Chat
+            }).unwrap();
+        }
+        collector.commit().unwrap();
+        let stats = collector.stats();
+        collector.abort().unwrap();
+        stats
+    }
+
+    /// Compares loading with COPY to loading with INSERT, run with
+    /// `TEST_DATABASE_URL=... cargo test -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn copy_loads_like_insert() {
+        let (connection, url) = test_database();
+        let count = 50_000;
+
+        let started = Instant::now();
+        let inserted = load_messages(&mut PgCollector::new(&connection), count);
+        let insert_time = started.elapsed();
+        let started = Instant::now();
+        let strategy = LoadStrategy::copy(&url).unwrap();
+        let copied = load_messages(&mut PgCollector::with_strategy(&connection, strategy), count);
+        let copy_time = started.elapsed();
+
+        assert_eq!(inserted, CollectorStats { inserted: count + 1, duplicates: 0 });
+        assert_eq!(copied, inserted);
+        let staged: i64 = sql::<BigInt>("SELECT count(*) FROM messages_staging")
+            .get_result(&connection)
+            .unwrap();
+        assert_eq!(staged, 0);
+        eprintln!("{} messages: insert {:?}, copy {:?}", count, insert_time, copy_time);
+    }
+
+    /// Checks that aborting an import only forgets the ids it created, run like the test above
+    #[test]
+    #[ignore]
+    fn abort_forgets_created_ids() {
+        let (connection, _) = test_database();
+        let mut collector = PgCollector::new(&connection);
+        let suffix = Utc::now().timestamp_subsec_nanos();
+        let message = |nick: String| RawMessage {
+            nick,
+            channel: "#_abort_test".to_owned(),
+            message: "test message".to_owned(),
+            sent_at: FixedOffset::east(2 * 3600).ymd(2017, 10, 5).and_hms(20, 0, 0),
+            flags: vec!(),
+            kind: MessageKind::Chat
+        };
+        let existing = format!("existing{}", suffix);
+        let created = format!("created{}", suffix);
+        collector.add_message(message(existing.clone())).unwrap();
+        collector.commit().unwrap();
+
+        let source = LogSource {
+            path: PathBuf::from("abort_test.log"),
+            content_hash: format!("abort test {}", suffix),
+            byte_size: 0
+        };
+        assert!(collector.begin(&source, "test").unwrap());
+        collector.add_message(message(existing.clone())).unwrap();
+        collector.add_message(message(created.clone())).unwrap();
+        collector.commit().unwrap();
+        collector.abort().unwrap();
+
+        assert!(collector.user_map.contains_key(&existing));
+        assert!(collector.channel_map.contains_key("#_abort_test"));
+        assert!(!collector.user_map.contains_key(&created));
+    }
+
+    /// Checks that a timeout clears the recent messages of its target, run like the tests above
+    #[test]
+    #[ignore]
+    fn timeouts_clear_recent_messages() {
+        let (connection, _) = test_database();
+        let mut collector = PgCollector::new(&connection);
+        let suffix = Utc::now().timestamp_subsec_nanos();
+        let nick = format!("ClearTest{}", suffix);
+        let at = |minute| Utc.ymd(2017, 10, 5).and_hms(20, minute, 0);
+        let message = |nick: &str, minute| RawMessage {
+            nick: nick.to_owned(),
+            channel: "#_clear_test".to_owned(),
+            message: format!("sent at {}", minute),
+            sent_at: at(minute).with_timezone(&FixedOffset::east(2 * 3600)),
+            flags: vec!(),
+            kind: MessageKind::Chat
+        };
+        let source = LogSource {
+            path: PathBuf::from("clear_test.log"),
+            content_hash: format!("clear test {}", suffix),
+            byte_size: 0
+        };
+
+        assert!(collector.begin(&source, "test").unwrap());
+        collector.add_message(message(&nick, 0)).unwrap();
+        collector.add_message(message(&nick, 15)).unwrap();
+        collector.add_message(message("someone_else", 15)).unwrap();
+        collector.add_moderation_action(RawModerationAction {
+            channel: "#_clear_test".to_owned(),
+            sent_at: at(20).naive_utc(),
+            kind: ModerationKind::Timeout,
+            target: nick.to_lowercase(),
+            duration: Some(600),
+            reason: None,
+            moderator: None
+        }).unwrap();
+        collector.commit().unwrap();
+
+        let deleted: Vec<String> = messages::table
+            .select(messages::message)
+            .filter(messages::import_id.eq(collector.import_id))
+            .filter(messages::deleted_at.eq(at(20)))
+            .load(&connection)
+            .unwrap();
+        assert_eq!(deleted, vec!("sent at 15"));
+        collector.abort().unwrap();
+    }
+
+    #[test]
+    fn copy_row_format() {
+        let message = NewMessage {
+            user_id: 3,
+            channel_id: 1,
+            message: "tab\there\\ and\nnewline".to_owned(),
+            sent_at: Utc.ymd(2017, 10, 5).and_hms(22, 10, 0),
+            flags: vec!(2, 3),
+            occurrence: 0,
+            import_id: None,
+            kind: 2,
+            session_id: Some(7),
+            utc_offset: 7200
+        };
+        let mut output = Vec::new();
+        write_copy_row(&mut output, &message).unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "3\t1\ttab\\there\\\\ and\\nnewline\t2017-10-05 22:10:00+00:00\t{2,3}\t0\t\\N\t2\t7\t7200\n");
     }
 }
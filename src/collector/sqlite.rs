@@ -0,0 +1,355 @@
+use chrono::NaiveDateTime;
+use collector::*;
+use diesel;
+use diesel::connection::TransactionManager;
+use diesel::prelude::*;
+use diesel::sqlite::SqliteConnection;
+use errors::{Error, Result};
+use input::LogSource;
+use models::{Channel, NewChannel, NewUser, User};
+use models::sqlite::{encode_flags, NewMessage};
+use schema::{channels, users};
+use schema::sqlite::messages;
+use std::collections::{BTreeSet, HashMap};
+
+embed_migrations!("migrations_sqlite");
+
+pub struct SqliteCollector<'a> {
+    batch_size: usize,
+    message_batch: Vec<NewMessage>,
+    channel_map: HashMap<String, i32>,
+    user_map: HashMap<String, i32>,
+    /// Names of the channels and users created by the current import, forgotten if it is aborted
+    created_channels: Vec<String>,
+    created_users: Vec<String>,
+    inserted: usize,
+    import_start: usize,
+    in_transaction: bool,
+    /// Kinds of records which were left out because SQLite archives do not store them
+    left_out: BTreeSet<&'static str>,
+    connection: &'a SqliteConnection
+}
+
+impl <'a> Collector for SqliteCollector<'a> {
+    #[allow(unused_variables)]
+    fn begin(&mut self, source: &LogSource, parser: &str) -> Result<bool> {
+        self.connection.transaction_manager().begin_transaction(self.connection)?;
+        self.in_transaction = true;
+        self.import_start = self.inserted;
+        self.created_channels.clear();
+        self.created_users.clear();
+        Ok(true)
+    }
+
+    fn add_message(&mut self, raw_message: RawMessage) -> Result<()> {
+        let new_user_id: i32 = self.find_or_create_user_id(raw_message.nick.as_ref())?;
+        let new_channel_id: i32 = self.find_or_create_channel_id(raw_message.channel.as_ref())?;
+
+        let new_message = NewMessage {
+            user_id: new_user_id,
+            channel_id: new_channel_id,
+            message: raw_message.message,
+            sent_at: raw_message.sent_at.naive_utc(),
+            flags: encode_flags(&raw_message.flags),
+            kind: raw_message.kind.into(),
+            utc_offset: raw_message.sent_at.offset().local_minus_utc()
+        };
+
+        self.message_batch.push(new_message);
+
+        if self.message_batch.len() >= self.batch_size {
+            self.commit()?;
+        }
+
+        Ok(())
+    }
+
+    /// Events are not archived in SQLite
+    #[allow(unused_variables)]
+    fn add_event(&mut self, raw_event: RawEvent) -> Result<()> {
+        self.leave_out("events");
+        Ok(())
+    }
+
+    /// Moderation actions are not archived in SQLite
+    #[allow(unused_variables)]
+    fn add_moderation_action(&mut self, raw_action: RawModerationAction) -> Result<()> {
+        self.leave_out("moderation actions");
+        Ok(())
+    }
+
+    /// Subscriptions are not archived in SQLite
+    #[allow(unused_variables)]
+    fn add_subscription(&mut self, raw_subscription: RawSubscription) -> Result<()> {
+        self.leave_out("subscriptions");
+        Ok(())
+    }
+
+    /// Sessions are not archived in SQLite
+    #[allow(unused_variables)]
+    fn begin_session(&mut self, started_at: NaiveDateTime) -> Result<()> {
+        self.leave_out("sessions");
+        Ok(())
+    }
+
+    #[allow(unused_variables)]
+    fn end_session(&mut self, raw_session: RawSession) -> Result<()> {
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        if self.message_batch.is_empty() {
+            return Ok(());
+        }
+        self.connection.transaction::<_, Error, _>(|| {
+            diesel::insert(&self.message_batch).into(messages::table)
+                .execute(self.connection)?;
+            Ok(())
+        })?;
+        self.inserted += self.message_batch.len();
+        self.message_batch.clear();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.commit()?;
+        if self.in_transaction {
+            self.in_transaction = false;
+            self.connection.transaction_manager().commit_transaction(self.connection)?;
+        }
+        Ok(())
+    }
+
+    fn abort(&mut self) -> Result<()> {
+        self.message_batch.clear();
+        self.inserted = self.import_start;
+        if self.in_transaction {
+            self.in_transaction = false;
+            // Users and channels created by this file are gone after the rollback
+            for name in self.created_users.drain(..) {
+                self.user_map.remove(&name);
+            }
+            for name in self.created_channels.drain(..) {
+                self.channel_map.remove(&name);
+            }
+            self.connection.transaction_manager().rollback_transaction(self.connection)?;
+        }
+        Ok(())
+    }
+
+    fn stats(&self) -> CollectorStats {
+        CollectorStats { inserted: self.inserted, duplicates: 0 }
+    }
+}
+
+impl <'a> SqliteCollector<'a> {
+    pub fn new(connection: &SqliteConnection) -> SqliteCollector {
+        SqliteCollector {
+            batch_size: DEFAULT_BATCH_SIZE,
+            message_batch: Vec::new(),
+            channel_map: HashMap::new(),
+            user_map: HashMap::new(),
+            created_channels: Vec::new(),
+            created_users: Vec::new(),
+            inserted: 0,
+            import_start: 0,
+            in_transaction: false,
+            left_out: BTreeSet::new(),
+            connection,
+        }
+    }
+
+    /// Warns the first time records of a kind are left out of the archive
+    fn leave_out(&mut self, records: &'static str) {
+        if self.left_out.insert(records) {
+            eprintln!("Warning: SQLite archives do not store {}, they are left out", records);
+        }
+    }
+
+    /// Creates or updates the archive tables in the given database
+    pub fn run_migrations(connection: &SqliteConnection) -> Result<()> {
+        embedded_migrations::run(connection)?;
+        Ok(())
+    }
+
+    fn find_user_by_nick(&self, nick: &str) -> Option<User> {
+        use self::users::dsl::*;
+        let user = users.filter(name.eq(nick))
+            .first(self.connection);
+
+        user.ok()
+    }
+
+    fn find_channel_by_name(&self, channel: &str) -> Option<Channel> {
+        use self::channels::dsl::*;
+        let channel = channels.filter(name.eq(channel))
+            .first(self.connection);
+
+        channel.ok()
+    }
+
+    fn find_or_create_channel_id(&mut self, name: &str) -> Result<i32> {
+        if let Some(id) = self.channel_map.get(name) {
+            return Ok(*id);
+        }
+        let channel = match self.find_channel_by_name(name) {
+            Some(channel) => channel,
+            None => {
+                // SQLite has no RETURNING, so the new row is looked up again after inserting
+                diesel::insert(&NewChannel { name }).into(channels::table)
+                    .execute(self.connection)?;
+                if self.in_transaction {
+                    self.created_channels.push(name.to_owned());
+                }
+                self.find_channel_by_name(name)
+                    .ok_or(diesel::result::Error::NotFound)?
+            }
+        };
+        self.channel_map.insert(name.to_owned(), channel.id);
+        Ok(channel.id)
+    }
+
+    fn find_or_create_user_id(&mut self, name: &str) -> Result<i32> {
+        if let Some(id) = self.user_map.get(name) {
+            return Ok(*id);
+        }
+        let user = match self.find_user_by_nick(name) {
+            Some(user) => user,
+            None => {
+                diesel::insert(&NewUser { name }).into(users::table)
+                    .execute(self.connection)?;
+                if self.in_transaction {
+                    self.created_users.push(name.to_owned());
+                }
+                self.find_user_by_nick(name)
+                    .ok_or(diesel::result::Error::NotFound)?
+            }
+        };
+        self.user_map.insert(name.to_owned(), user.id);
+        Ok(user.id)
+    }
+}
+
+/// A file whose import was neither finished nor aborted is rolled back, so an interrupted import
+/// leaves nothing behind. Messages added outside of an import are stored like by `commit`.
+impl <'a> Drop for SqliteCollector<'a> {
+    fn drop(&mut self) {
+        if self.in_transaction {
+            if let Err(err) = self.abort() {
+                eprintln!("Rolling back unfinished import failed: {}", err);
+            }
+        } else if let Err(err) = self.commit() {
+            eprintln!("Storing pending messages failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{FixedOffset, TimeZone};
+    use models::sqlite::decode_flags;
+    use models::types::{MessageFlag, MessageKind};
+    use std::path::PathBuf;
+
+    fn archive() -> SqliteConnection {
+        let connection = SqliteConnection::establish(":memory:").unwrap();
+        SqliteCollector::run_migrations(&connection).unwrap();
+        connection
+    }
+
+    fn source() -> LogSource {
+        LogSource { path: PathBuf::from("chatty.log"), content_hash: "hash".to_owned(), byte_size: 0 }
+    }
+
+    fn message(nick: &str, flags: Vec<MessageFlag>) -> RawMessage {
+        RawMessage {
+            nick: nick.to_owned(),
+            channel: "#_cerebot".to_owned(),
+            message: "test message".to_owned(),
+            sent_at: FixedOffset::east(2 * 3600).ymd(2017, 10, 5).and_hms(21, 50, 0),
+            flags,
+            kind: MessageKind::Chat
+        }
+    }
+
+    fn count_messages(connection: &SqliteConnection) -> i64 {
+        messages::table.count().get_result(connection).unwrap()
+    }
+
+    fn count_users(connection: &SqliteConnection) -> i64 {
+        users::table.count().get_result(connection).unwrap()
+    }
+
+    #[test]
+    fn run_embedded_migrations() {
+        let connection = archive();
+        assert_eq!(count_messages(&connection), 0);
+        assert_eq!(count_users(&connection), 0);
+        // Running them again leaves the archive as it is
+        SqliteCollector::run_migrations(&connection).unwrap();
+    }
+
+    #[test]
+    fn find_or_create_users_and_channels() {
+        let connection = archive();
+        let mut collector = SqliteCollector::new(&connection);
+        let john = collector.find_or_create_user_id("JohnDoe").unwrap();
+        let jane = collector.find_or_create_user_id("JaneDoe").unwrap();
+        let channel = collector.find_or_create_channel_id("#_cerebot").unwrap();
+        assert!(john != jane);
+
+        // A new collector finds the stored rows instead of creating them again
+        let mut collector = SqliteCollector::new(&connection);
+        assert_eq!(collector.find_or_create_user_id("JohnDoe").unwrap(), john);
+        assert_eq!(collector.find_or_create_channel_id("#_cerebot").unwrap(), channel);
+        assert_eq!(count_users(&connection), 2);
+    }
+
+    #[test]
+    fn store_flags_as_codes() {
+        let connection = archive();
+        let flags = vec!(MessageFlag::Moderator, MessageFlag::Subscriber);
+        {
+            let mut collector = SqliteCollector::new(&connection);
+            collector.add_message(message("JohnDoe", flags.clone())).unwrap();
+            collector.add_message(message("JaneDoe", vec!())).unwrap();
+            collector.commit().unwrap();
+        }
+
+        let stored: Vec<String> = messages::table.select(messages::flags)
+            .order(messages::id)
+            .load(&connection)
+            .unwrap();
+        assert_eq!(stored, vec!("2,4", ""));
+        assert_eq!(decode_flags(&stored[0]), flags);
+        assert_eq!(decode_flags(&stored[1]), vec!());
+    }
+
+    #[test]
+    fn store_pending_messages_on_drop() {
+        let connection = archive();
+        SqliteCollector::new(&connection).add_message(message("JohnDoe", vec!())).unwrap();
+        assert_eq!(count_messages(&connection), 1);
+    }
+
+    #[test]
+    fn abort_rolls_back_import() {
+        let connection = archive();
+        let mut collector = SqliteCollector::new(&connection);
+        let jane = collector.find_or_create_user_id("JaneDoe").unwrap();
+        collector.begin(&source(), "chatty").unwrap();
+        collector.add_message(message("JohnDoe", vec!())).unwrap();
+        collector.commit().unwrap();
+        assert_eq!(count_messages(&connection), 1);
+        collector.abort().unwrap();
+
+        assert_eq!(count_messages(&connection), 0);
+        assert_eq!(count_users(&connection), 1);
+        assert_eq!(collector.stats().inserted, 0);
+        // Only the ids of rows created by the aborted import are forgotten
+        assert!(!collector.user_map.contains_key("JohnDoe"));
+        assert_eq!(collector.user_map.get("JaneDoe"), Some(&jane));
+        assert!(collector.find_or_create_user_id("JohnDoe").unwrap() != jane);
+    }
+}
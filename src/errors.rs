@@ -7,9 +7,16 @@ error_chain! {
 
     foreign_links {
         Diesel(::diesel::result::Error);
+        Postgres(::postgres::Error);
+        Migration(::diesel::migrations::RunMigrationsError);
         IoError(::std::io::Error);
         ChronoParseError(::chrono::ParseError);
         NomError(::nom::ErrorKind);
+        Csv(::csv::Error);
+        Json(::serde_json::Error);
+        Pattern(::glob::PatternError);
+        Glob(::glob::GlobError);
+        Regex(::regex::Error);
     }
 
     errors {
@@ -30,5 +37,17 @@ error_chain! {
             description("Line ended prematurely, parsing incomplete")
             display("Parsing Error: Line {} was incomplete", line_num)
         }
+        RejectedLine(line_num: usize, reason: String) {
+            description("Line could not be parsed or placed in the log")
+            display("Rejected line {}: {}", line_num, reason)
+        }
+        TimestampAnomaly(line_num: usize, anomaly: String) {
+            description("Implausible jump in the log's timestamps")
+            display("Timestamp anomaly in line {}: {}", line_num, anomaly)
+        }
+        NoSuchInput(path: ::std::path::PathBuf) {
+            description("Input is neither a file, a directory nor a matching pattern")
+            display("No log files found for '{}'", path.display())
+        }
     }
 }
@@ -0,0 +1,461 @@
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use errors::{ErrorKind, Result};
+use flate2::bufread::MultiGzDecoder;
+use glob::glob;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use xz2::bufread::XzDecoder;
+use zstd::stream::read::Decoder as ZstdDecoder;
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::str;
+
+/// Compression formats recognised in log files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz
+}
+
+impl Compression {
+    /// Detects the compression format from the first bytes of a file
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens a log file for reading, decompressing it on the fly if it is compressed
+pub fn open_log(path: &Path) -> Result<Box<BufRead>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let compression = Compression::detect(reader.fill_buf()?);
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(ZstdDecoder::with_buffer(reader)?)),
+        Compression::Xz => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+    })
+}
+
+/// How bytes which are not valid UTF-8 are decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fallback {
+    /// Each invalid sequence is replaced by U+FFFD
+    Replace,
+    /// Each invalid byte is read as ISO 8859-1
+    Latin1,
+    /// Each invalid byte is read as Windows-1252
+    Cp1252
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Fallback::Replace
+    }
+}
+
+/// Characters of Windows-1252 which differ from ISO 8859-1, for the bytes 0x80 to 0x9F.
+///
+/// Bytes undefined in Windows-1252 keep their C1 control character, as browsers do.
+static CP1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}'
+];
+
+/// A line of a log decoded into text, together with the damage repaired on the way
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedLine {
+    pub text: String,
+    /// Number of NUL bytes removed, as left behind by crashes
+    pub nul_bytes: usize,
+    /// Number of bytes which were not valid UTF-8 and decoded with the fallback instead
+    pub invalid_bytes: usize
+}
+
+impl DecodedLine {
+    /// Decodes a line without its line ending.
+    ///
+    /// NUL bytes are removed, valid UTF-8 is kept as it is and everything else is decoded
+    /// with `fallback`.
+    pub fn decode(bytes: &[u8], fallback: Fallback) -> DecodedLine {
+        let without_nul: Vec<u8> = bytes.iter().cloned().filter(|&byte| byte != 0).collect();
+        let nul_bytes = bytes.len() - without_nul.len();
+        let mut text = String::with_capacity(without_nul.len());
+        let mut invalid_bytes = 0;
+        let mut rest = &without_nul[..];
+
+        loop {
+            match str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                },
+                Err(err) => {
+                    let (valid, after) = rest.split_at(err.valid_up_to());
+                    text.push_str(&String::from_utf8_lossy(valid));
+                    let invalid_len = err.error_len().unwrap_or(after.len());
+                    match fallback {
+                        Fallback::Replace => text.push('\u{FFFD}'),
+                        Fallback::Latin1 =>
+                            text.extend(after[..invalid_len].iter().map(|&byte| byte as char)),
+                        Fallback::Cp1252 =>
+                            text.extend(after[..invalid_len].iter().map(|&byte| cp1252_char(byte))),
+                    }
+                    invalid_bytes += invalid_len;
+                    rest = &after[invalid_len..];
+                }
+            }
+        }
+
+        DecodedLine { text, nul_bytes, invalid_bytes }
+    }
+
+    /// Whether anything had to be repaired to read the line
+    pub fn is_repaired(&self) -> bool {
+        self.nul_bytes > 0 || self.invalid_bytes > 0
+    }
+}
+
+fn cp1252_char(byte: u8) -> char {
+    match byte {
+        0x80..=0x9f => CP1252_HIGH[(byte - 0x80) as usize],
+        _ => byte as char
+    }
+}
+
+/// Iterator over the lines of a log which does not stop at damaged bytes
+pub struct DecodedLines<R: BufRead> {
+    input: R,
+    fallback: Fallback,
+    buffer: Vec<u8>
+}
+
+impl <R: BufRead> DecodedLines<R> {
+    pub fn new(input: R, fallback: Fallback) -> DecodedLines<R> {
+        DecodedLines { input, fallback, buffer: Vec::new() }
+    }
+}
+
+impl <R: BufRead> Iterator for DecodedLines<R> {
+    type Item = Result<DecodedLine>;
+
+    fn next(&mut self) -> Option<Result<DecodedLine>> {
+        self.buffer.clear();
+        match self.input.read_until(b'\n', &mut self.buffer) {
+            Ok(0) => None,
+            Ok(_) => {
+                if self.buffer.ends_with(b"\n") {
+                    self.buffer.pop();
+                }
+                if self.buffer.ends_with(b"\r") {
+                    self.buffer.pop();
+                }
+                Some(Ok(DecodedLine::decode(&self.buffer, self.fallback)))
+            },
+            Err(err) => Some(Err(err.into()))
+        }
+    }
+}
+
+/// Patterns used when no others are configured, for names like `#channel-2017-10-05.log`
+static DEFAULT_HINT_PATTERNS: [&str; 2] = [
+    r"(?P<channel>#[^/\\]+?)-(?P<date>\d{4}-\d{2}-\d{2})\.log",
+    r"(?P<channel>#[^/\\]+?)\.log",
+];
+
+/// What the path of a log file tells about the log
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileHints {
+    pub channel: Option<String>,
+    pub date: Option<NaiveDate>,
+    pub zone: Option<Tz>
+}
+
+/// Regular expressions which extract `FileHints` from the paths of log files.
+///
+/// Each pattern is searched for in the whole path and may capture the named groups `channel`,
+/// `date` as `YYYY-MM-DD` and `zone` as an IANA zone name. The first matching pattern is used.
+#[derive(Debug, Clone)]
+pub struct HintPatterns {
+    patterns: Vec<Regex>
+}
+
+impl HintPatterns {
+    pub fn new<S: AsRef<str>>(patterns: &[S]) -> Result<HintPatterns> {
+        let patterns = patterns.iter()
+            .map(|pattern| Regex::new(pattern.as_ref()))
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        Ok(HintPatterns { patterns })
+    }
+
+    /// Extracts the hints from the path of a log file
+    pub fn hints(&self, path: &Path) -> FileHints {
+        let path = path.to_string_lossy();
+        let captures = match self.patterns.iter().filter_map(|pattern| pattern.captures(&path)).next() {
+            Some(captures) => captures,
+            None => return FileHints::default()
+        };
+        FileHints {
+            channel: captures.name("channel").map(|channel| channel.as_str().to_owned()),
+            date: captures.name("date")
+                .and_then(|date| NaiveDate::parse_from_str(date.as_str(), "%Y-%m-%d").ok()),
+            zone: captures.name("zone").and_then(|zone| zone.as_str().parse().ok())
+        }
+    }
+}
+
+impl Default for HintPatterns {
+    fn default() -> Self {
+        HintPatterns::new(&DEFAULT_HINT_PATTERNS).expect("default hint patterns are valid")
+    }
+}
+
+/// Identity of a log file, recorded as the origin of imported messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogSource {
+    pub path: PathBuf,
+    /// Hex encoded SHA-256 of the file as stored on disk
+    pub content_hash: String,
+    pub byte_size: u64
+}
+
+impl LogSource {
+    /// Reads the file once to determine its size and content hash
+    pub fn from_path(path: &Path) -> Result<LogSource> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::default();
+        let mut byte_size = 0;
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.input(&buffer[..read]);
+            byte_size += read as u64;
+        }
+
+        Ok(LogSource {
+            path: path.to_owned(),
+            content_hash: format!("{:x}", hasher.result()),
+            byte_size
+        })
+    }
+}
+
+/// Log files found for a set of input paths
+#[derive(Debug, Default)]
+pub struct LogFiles {
+    /// Files to import, sorted by path
+    pub files: Vec<PathBuf>,
+    /// Files found while walking directories which do not look like logs
+    pub skipped: Vec<PathBuf>
+}
+
+/// Resolves files, directories and glob patterns into the log files to import.
+///
+/// Files named explicitly are always imported. Directories are walked recursively and only files
+/// with a log extension, optionally followed by `.gz`, `.zst` or `.xz`, are picked up. Everything
+/// else is reported as skipped. Inputs which do not exist on disk are treated as glob patterns.
+/// Symbolic links are followed, but every directory is only walked once, so links pointing back
+/// up the tree do not loop.
+pub fn find_log_files(inputs: &[PathBuf]) -> Result<LogFiles> {
+    let mut found = LogFiles::default();
+    let mut visited = HashSet::new();
+
+    for input in inputs {
+        if input.exists() {
+            add_path(input, true, &mut found, &mut visited)?;
+        } else {
+            let pattern = input.to_string_lossy();
+            let mut matched = false;
+            for path in glob(&pattern)? {
+                add_path(&path?, true, &mut found, &mut visited)?;
+                matched = true;
+            }
+            if !matched {
+                Err(ErrorKind::NoSuchInput(input.to_owned()))?
+            }
+        }
+    }
+
+    found.files.sort();
+    found.files.dedup();
+    found.skipped.sort();
+    found.skipped.dedup();
+    // A file named explicitly may also have been skipped while walking its directory
+    let LogFiles { ref files, ref mut skipped } = found;
+    skipped.retain(|path| files.binary_search(path).is_err());
+    Ok(found)
+}
+
+/// Adds a file or walks a directory, skipping directories in `visited` by their canonical path
+fn add_path(path: &Path, explicit: bool, found: &mut LogFiles, visited: &mut HashSet<PathBuf>)
+    -> Result<()> {
+    if path.is_dir() {
+        if !visited.insert(path.canonicalize()?) {
+            return Ok(());
+        }
+        for entry in fs::read_dir(path)? {
+            add_path(&entry?.path(), false, found, visited)?;
+        }
+    } else if explicit || is_log_file(path) {
+        found.files.push(path.to_owned());
+    } else {
+        found.skipped.push(path.to_owned());
+    }
+    Ok(())
+}
+
+/// Whether the file name looks like a Chatty log, possibly compressed
+fn is_log_file(path: &Path) -> bool {
+    let path = match path.extension() {
+        Some(ext) if ext == "gz" || ext == "zst" || ext == "xz" => path.with_extension(""),
+        _ => path.to_owned()
+    };
+    path.extension().map_or(false, |ext| ext == "log")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    /// Creates an empty directory for a test, replacing what an earlier run left behind
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("twitch_archiver_{}", name));
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(dir: &Path, name: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap();
+        path
+    }
+
+    #[test]
+    fn walk_directories() {
+        let dir = test_dir("walk");
+        let log = touch(&dir, "#a.log");
+        let nested = touch(&dir, "2017/#b.log.gz");
+        let readme = touch(&dir, "2017/README");
+        let explicit = touch(&dir, "notes.txt");
+
+        let found = find_log_files(&[dir.clone(), explicit.clone()]).unwrap();
+        assert_eq!(found.files, vec!(log, nested, explicit));
+        assert_eq!(found.skipped, vec!(readme));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn expand_glob_patterns() {
+        let dir = test_dir("glob");
+        let first = touch(&dir, "#a-2017-10-05.log");
+        let second = touch(&dir, "#a-2017-10-06.log");
+        touch(&dir, "#b-2017-10-05.log");
+
+        let found = find_log_files(&[dir.join("#a-*.log")]).unwrap();
+        assert_eq!(found.files, vec!(first, second));
+        assert!(found.skipped.is_empty());
+        match find_log_files(&[dir.join("#c-*.log")]).unwrap_err().kind() {
+            &ErrorKind::NoSuchInput(_) => {},
+            kind => panic!("unexpected error {:?}", kind)
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk_symlink_loops_once() {
+        use std::os::unix::fs::symlink;
+
+        let dir = test_dir("loop");
+        let log = touch(&dir, "logs/#a.log");
+        symlink(&dir, dir.join("logs/self")).unwrap();
+
+        let found = find_log_files(&[dir.join("logs")]).unwrap();
+        assert_eq!(found.files, vec!(log));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn log_file_extensions() {
+        assert!(is_log_file(Path::new("logs/#_cerebot.log")));
+        assert!(!is_log_file(Path::new("logs/#_cerebot.txt")));
+        assert!(!is_log_file(Path::new("logs/README")));
+        assert!(is_log_file(Path::new("logs/#_cerebot.log.gz")));
+        assert!(is_log_file(Path::new("logs/#_cerebot.log.zst")));
+        assert!(is_log_file(Path::new("logs/#_cerebot.log.xz")));
+        assert!(!is_log_file(Path::new("logs/backup.tar.gz")));
+    }
+
+    #[test]
+    fn decode_damaged_lines() {
+        let line = DecodedLine::decode(b"<JohnDoe> caf\xc3\xa9\x00\x00", Fallback::Replace);
+        assert_eq!(line, DecodedLine { text: "<JohnDoe> café".to_owned(), nul_bytes: 2, invalid_bytes: 0 });
+
+        let truncated = b"caf\xc3\xa9 \x93quoted\x94 \xe2\x82";
+        let decode = |fallback| DecodedLine::decode(truncated, fallback);
+        assert_eq!(decode(Fallback::Replace).text, "café \u{FFFD}quoted\u{FFFD} \u{FFFD}");
+        assert_eq!(decode(Fallback::Latin1).text, "café \u{93}quoted\u{94} \u{e2}\u{82}");
+        assert_eq!(decode(Fallback::Cp1252).text, "café \u{201C}quoted\u{201D} \u{e2}\u{201A}");
+        assert_eq!(decode(Fallback::Cp1252).invalid_bytes, 4);
+    }
+
+    #[test]
+    fn read_lines_past_invalid_bytes() {
+        let input: &[u8] = b"first\r\nsec\xffond\n\x00\x00\x00\nlast";
+        let lines = DecodedLines::new(input, Fallback::Latin1)
+            .map(|line| line.unwrap().text)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!("first", "sec\u{ff}ond", "", "last"));
+    }
+
+    #[test]
+    fn hints_from_file_names() {
+        let patterns = HintPatterns::default();
+        assert_eq!(patterns.hints(Path::new("logs/#_cerebot-2017-10-05.log.gz")), FileHints {
+            channel: Some("#_cerebot".to_owned()),
+            date: Some(NaiveDate::from_ymd(2017, 10, 5)),
+            zone: None
+        });
+        assert_eq!(patterns.hints(Path::new("logs/#_cerebot.log")).channel, Some("#_cerebot".to_owned()));
+        assert_eq!(patterns.hints(Path::new("logs/chatty.log")), FileHints::default());
+
+        let patterns = HintPatterns::new(&[r"(?P<zone>\w+/\w+)/(?P<date>[\d-]+)\.log"]).unwrap();
+        let hints = patterns.hints(Path::new("logs/Europe/Berlin/2017-10-05.log"));
+        assert_eq!(hints.zone, Some("Europe/Berlin".parse().unwrap()));
+        assert_eq!(hints.date, Some(NaiveDate::from_ymd(2017, 10, 5)));
+    }
+
+    #[test]
+    fn detect_compression() {
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08, 0x00]), Compression::Gzip);
+        assert_eq!(Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]), Compression::Zstd);
+        assert_eq!(Compression::detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]), Compression::Xz);
+        assert_eq!(Compression::detect(b"# Log started"), Compression::None);
+        assert_eq!(Compression::detect(b""), Compression::None);
+    }
+}
@@ -4,10 +4,25 @@
 #[macro_use] extern crate nom;
 #[macro_use] extern crate diesel_codegen;
 #[macro_use] extern crate diesel;
+#[macro_use] extern crate serde_derive;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate csv;
+extern crate serde;
+extern crate serde_json;
+extern crate glob;
+extern crate flate2;
+extern crate zstd;
+extern crate xz2;
+extern crate sha2;
+extern crate postgres;
+extern crate regex;
 
 pub mod schema;
 pub mod models;
 pub mod parser;
 pub mod collector;
 pub mod errors;
+pub mod input;
+pub mod report;
+pub mod repair;
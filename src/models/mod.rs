@@ -1,9 +1,10 @@
-
+use chrono::{DateTime, Utc};
 use chrono::naive::NaiveDateTime;
 use schema::*;
-use self::types::MessageFlag;
+use self::types::{MessageFlag, MessageKind, ModerationKind, SubscriptionKind, SubscriptionTier};
 
 pub mod types;
+pub mod sqlite;
 
 #[derive(Debug, Identifiable, Queryable)]
 pub struct User {
@@ -29,16 +30,75 @@ pub struct NewChannel<'a> {
     pub name: &'a str
 }
 
+#[derive(Debug, Identifiable, Queryable)]
+pub struct Import {
+    pub id: i32,
+    pub source_path: String,
+    pub content_hash: String,
+    pub byte_size: i64,
+    pub started_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    pub parser: String,
+    pub message_count: i32,
+    pub duplicate_count: i32
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="imports"]
+pub struct NewImport<'a> {
+    pub source_path: &'a str,
+    pub content_hash: &'a str,
+    pub byte_size: i64,
+    pub started_at: NaiveDateTime,
+    pub parser: &'a str
+}
+
+/// A run of the logging client
+#[derive(Debug, Identifiable, Queryable, Associations, PartialEq)]
+#[belongs_to(Channel)]
+#[belongs_to(Import)]
+pub struct Session {
+    pub id: i32,
+    pub channel_id: Option<i32>,
+    pub started_at: NaiveDateTime,
+    /// Missing if the client stopped without closing the log
+    pub ended_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub message_count: i32,
+    pub import_id: Option<i32>,
+    /// Path of the log file the session was recorded in
+    pub source_path: Option<String>
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="sessions"]
+pub struct NewSession<'a> {
+    pub started_at: NaiveDateTime,
+    pub import_id: Option<i32>,
+    pub source_path: Option<&'a str>
+}
+
 #[derive(Debug, Identifiable, Queryable, Associations, PartialEq)]
 #[belongs_to(User)]
 #[belongs_to(Channel)]
+#[belongs_to(Import)]
+#[belongs_to(Session)]
 pub struct Message {
     pub id: i32,
     pub user_id: i32,
     pub channel_id: i32,
     pub message: String,
-    pub sent_at: NaiveDateTime,
-    pub flags: Vec<MessageFlag>
+    pub sent_at: DateTime<Utc>,
+    pub flags: Vec<MessageFlag>,
+    pub occurrence: i16,
+    pub import_id: Option<i32>,
+    /// When the message was cleared from chat by a timeout or ban
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub deleted_by_action: Option<i32>,
+    pub kind: MessageKind,
+    pub session_id: Option<i32>,
+    /// Offset from UTC in seconds the message was logged with, unknown for old imports
+    pub utc_offset: Option<i32>
 }
 
 #[derive(Debug, Insertable)]
@@ -47,6 +107,94 @@ pub struct NewMessage {
     pub user_id: i32,
     pub channel_id: i32,
     pub message: String,
+    pub sent_at: DateTime<Utc>,
+    pub flags: Vec<i16>,
+    pub occurrence: i16,
+    pub import_id: Option<i32>,
+    pub kind: i16,
+    pub session_id: Option<i32>,
+    pub utc_offset: i32
+}
+
+#[derive(Debug, Identifiable, Queryable, Associations, PartialEq)]
+#[belongs_to(Channel)]
+#[belongs_to(Import)]
+pub struct Event {
+    pub id: i32,
+    pub channel_id: Option<i32>,
+    pub sent_at: NaiveDateTime,
+    pub message: String,
+    pub import_id: Option<i32>
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="events"]
+pub struct NewEvent {
+    pub channel_id: Option<i32>,
+    pub sent_at: NaiveDateTime,
+    pub message: String,
+    pub import_id: Option<i32>
+}
+
+#[derive(Debug, Identifiable, Queryable, Associations, PartialEq)]
+#[belongs_to(Channel)]
+#[belongs_to(User, foreign_key = "target_id")]
+#[belongs_to(Import)]
+pub struct ModerationAction {
+    pub id: i32,
+    pub channel_id: i32,
+    pub target_id: i32,
+    pub moderator_id: Option<i32>,
+    pub kind: ModerationKind,
+    /// Length of a timeout in seconds
+    pub duration: Option<i32>,
+    pub reason: Option<String>,
+    pub sent_at: NaiveDateTime,
+    pub import_id: Option<i32>
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="moderation_actions"]
+pub struct NewModerationAction {
+    pub channel_id: i32,
+    pub target_id: i32,
+    pub moderator_id: Option<i32>,
+    pub kind: i16,
+    pub duration: Option<i32>,
+    pub reason: Option<String>,
+    pub sent_at: NaiveDateTime,
+    pub import_id: Option<i32>
+}
+
+#[derive(Debug, Identifiable, Queryable, Associations, PartialEq)]
+#[belongs_to(Channel)]
+#[belongs_to(User, foreign_key = "subscriber_id")]
+#[belongs_to(Import)]
+pub struct Subscription {
+    pub id: i32,
+    pub channel_id: i32,
+    /// The subscribing user, or the gifter of a gifted sub
+    pub subscriber_id: i32,
+    /// Receiver of a gifted sub
+    pub recipient_id: Option<i32>,
+    pub kind: SubscriptionKind,
+    pub tier: SubscriptionTier,
+    pub months: i32,
+    pub message: Option<String>,
+    pub sent_at: NaiveDateTime,
+    pub import_id: Option<i32>
+}
+
+#[derive(Debug, Insertable)]
+#[table_name="subscriptions"]
+pub struct NewSubscription {
+    pub channel_id: i32,
+    pub subscriber_id: i32,
+    pub recipient_id: Option<i32>,
+    pub kind: i16,
+    pub tier: i16,
+    pub months: i32,
+    pub message: Option<String>,
     pub sent_at: NaiveDateTime,
-    pub flags: Vec<i16>
+    pub import_id: Option<i32>
 }
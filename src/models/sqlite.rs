@@ -0,0 +1,33 @@
+use chrono::naive::NaiveDateTime;
+use models::types::MessageFlag;
+use schema::sqlite::messages;
+
+/// Message as stored in SQLite, which lacks array columns
+#[derive(Debug, Insertable)]
+#[table_name="messages"]
+pub struct NewMessage {
+    pub user_id: i32,
+    pub channel_id: i32,
+    pub message: String,
+    pub sent_at: NaiveDateTime,
+    pub flags: String,
+    pub kind: i16,
+    /// Offset from UTC in seconds, `sent_at` is in UTC
+    pub utc_offset: i32
+}
+
+/// Encodes message flags as their comma separated codes, e.g. `2,3`
+pub fn encode_flags(flags: &[MessageFlag]) -> String {
+    flags.iter()
+        .map(|&flag| i16::from(flag).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Decodes flags encoded by `encode_flags`, leaving out unknown codes
+pub fn decode_flags(flags: &str) -> Vec<MessageFlag> {
+    flags.split(',')
+        .filter_map(|code| code.parse().ok())
+        .filter_map(MessageFlag::from_code)
+        .collect()
+}
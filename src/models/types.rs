@@ -2,8 +2,10 @@ use diesel::pg::Pg;
 use diesel::row::Row;
 use diesel::types::*;
 use std::error::Error;
+use std::fmt;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 #[repr(i16)]
 pub enum MessageFlag {
     Broadcaster = 1,
@@ -13,6 +15,20 @@ pub enum MessageFlag {
     Staff = 5
 }
 
+impl MessageFlag {
+    /// The flag stored as `code`, if there is one
+    pub fn from_code(code: i16) -> Option<MessageFlag> {
+        match code {
+            1 => Some(MessageFlag::Broadcaster),
+            2 => Some(MessageFlag::Moderator),
+            3 => Some(MessageFlag::Prime),
+            4 => Some(MessageFlag::Subscriber),
+            5 => Some(MessageFlag::Staff),
+            _ => None
+        }
+    }
+}
+
 /*impl Into<i16> for MessageFlag {
     fn into(self) -> i16 {
         self as i16
@@ -25,15 +41,185 @@ impl From<MessageFlag> for i16 {
     }
 }
 
+impl fmt::Display for MessageFlag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            MessageFlag::Broadcaster => "broadcaster",
+            MessageFlag::Moderator => "moderator",
+            MessageFlag::Prime => "prime",
+            MessageFlag::Subscriber => "subscriber",
+            MessageFlag::Staff => "staff",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 impl FromSqlRow<VarChar, Pg> for MessageFlag {
+    fn build_from_row<R: Row<Pg>>(row: &mut R) -> Result<Self, Box<Error + Send + Sync>> {
+        let v = i16::build_from_row(row)?;
+        MessageFlag::from_code(v)
+            .ok_or_else(|| format!("Unknown value {} for MessageFlag found", v).into())
+    }
+}
+
+/// What a moderator did to a user
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i16)]
+pub enum ModerationKind {
+    Timeout = 1,
+    Ban = 2,
+    Untimeout = 3,
+    Unban = 4
+}
+
+impl From<ModerationKind> for i16 {
+    fn from(kind: ModerationKind) -> Self {
+        kind as i16
+    }
+}
+
+impl fmt::Display for ModerationKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            ModerationKind::Timeout => "timeout",
+            ModerationKind::Ban => "ban",
+            ModerationKind::Untimeout => "untimeout",
+            ModerationKind::Unban => "unban",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromSqlRow<SmallInt, Pg> for ModerationKind {
+    fn build_from_row<R: Row<Pg>>(row: &mut R) -> Result<Self, Box<Error + Send + Sync>> {
+        match i16::build_from_row(row)? {
+            1 => Ok(ModerationKind::Timeout),
+            2 => Ok(ModerationKind::Ban),
+            3 => Ok(ModerationKind::Untimeout),
+            4 => Ok(ModerationKind::Unban),
+            v => Err(format!("Unknown value {} for ModerationKind found", v).into()),
+        }
+    }
+}
+
+/// How a subscription came about
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i16)]
+pub enum SubscriptionKind {
+    Sub = 1,
+    Resub = 2,
+    Gift = 3
+}
+
+impl From<SubscriptionKind> for i16 {
+    fn from(kind: SubscriptionKind) -> Self {
+        kind as i16
+    }
+}
+
+impl fmt::Display for SubscriptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            SubscriptionKind::Sub => "sub",
+            SubscriptionKind::Resub => "resub",
+            SubscriptionKind::Gift => "gift",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromSqlRow<SmallInt, Pg> for SubscriptionKind {
+    fn build_from_row<R: Row<Pg>>(row: &mut R) -> Result<Self, Box<Error + Send + Sync>> {
+        match i16::build_from_row(row)? {
+            1 => Ok(SubscriptionKind::Sub),
+            2 => Ok(SubscriptionKind::Resub),
+            3 => Ok(SubscriptionKind::Gift),
+            v => Err(format!("Unknown value {} for SubscriptionKind found", v).into()),
+        }
+    }
+}
+
+/// Paid tier of a subscription
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i16)]
+pub enum SubscriptionTier {
+    Tier1 = 1,
+    Tier2 = 2,
+    Tier3 = 3,
+    Prime = 4
+}
+
+impl From<SubscriptionTier> for i16 {
+    fn from(tier: SubscriptionTier) -> Self {
+        tier as i16
+    }
+}
+
+impl fmt::Display for SubscriptionTier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            SubscriptionTier::Tier1 => "tier1",
+            SubscriptionTier::Tier2 => "tier2",
+            SubscriptionTier::Tier3 => "tier3",
+            SubscriptionTier::Prime => "prime",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromSqlRow<SmallInt, Pg> for SubscriptionTier {
+    fn build_from_row<R: Row<Pg>>(row: &mut R) -> Result<Self, Box<Error + Send + Sync>> {
+        match i16::build_from_row(row)? {
+            1 => Ok(SubscriptionTier::Tier1),
+            2 => Ok(SubscriptionTier::Tier2),
+            3 => Ok(SubscriptionTier::Tier3),
+            4 => Ok(SubscriptionTier::Prime),
+            v => Err(format!("Unknown value {} for SubscriptionTier found", v).into()),
+        }
+    }
+}
+
+/// How a message was sent
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i16)]
+pub enum MessageKind {
+    Chat = 1,
+    /// Sent with `/me`
+    Action = 2,
+    Whisper = 3,
+    Notice = 4
+}
+
+impl From<MessageKind> for i16 {
+    fn from(kind: MessageKind) -> Self {
+        kind as i16
+    }
+}
+
+impl fmt::Display for MessageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            MessageKind::Chat => "chat",
+            MessageKind::Action => "action",
+            MessageKind::Whisper => "whisper",
+            MessageKind::Notice => "notice",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromSqlRow<SmallInt, Pg> for MessageKind {
     fn build_from_row<R: Row<Pg>>(row: &mut R) -> Result<Self, Box<Error + Send + Sync>> {
         match i16::build_from_row(row)? {
-            1 => Ok(MessageFlag::Broadcaster),
-            2 => Ok(MessageFlag::Moderator),
-            3 => Ok(MessageFlag::Prime),
-            4 => Ok(MessageFlag::Subscriber),
-            5 => Ok(MessageFlag::Staff),
-            v => Err(format!("Unknown value {} for MessageFlag found", v).into()),
+            1 => Ok(MessageKind::Chat),
+            2 => Ok(MessageKind::Action),
+            3 => Ok(MessageKind::Whisper),
+            4 => Ok(MessageKind::Notice),
+            v => Err(format!("Unknown value {} for MessageKind found", v).into()),
         }
     }
 }
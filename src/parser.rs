@@ -1,22 +1,379 @@
 #![allow(dead_code)]
 
-use models::types::MessageFlag;
-use collector::{Collector, RawMessage};
+use models::types::{MessageFlag, MessageKind, ModerationKind, SubscriptionKind, SubscriptionTier};
+use collector::{Collector, RawEvent, RawMessage, RawModerationAction, RawSession, RawSubscription};
 use errors::{Result, ErrorKind};
+use input::{DecodedLines, Fallback, FileHints};
 
 use chrono::prelude::*;
 use chrono::Duration;
+use chrono_tz::Tz;
 use nom::{IResult, not_line_ending, digit, space, line_ending};
 
+use std::collections::BTreeMap;
+use std::fmt;
 use std::io::BufRead;
+use std::mem;
 use std::str::FromStr;
 use std::ops::Add;
 
 pub trait LogParser {
-    fn parse<T: BufRead + ? Sized>(collector: &mut Collector, input: T) -> Result<()> where T: Sized;
+    /// Name of the log format, recorded with every import
+    fn name() -> &'static str;
+    fn parse<T: BufRead + ? Sized>(&self, collector: &mut Collector, input: T) -> Result<ParseReport>
+        where T: Sized;
 }
 
-pub struct ChattyParser {}
+/// How dates are inferred for timestamps which only contain a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateInference {
+    /// The next day is assumed whenever the time goes backwards by more than a clock change
+    Rollover,
+    /// Like `Rollover`, but each session is buffered and its dates are kept between its
+    /// `Log started` and `Log closed` markers
+    Bounded
+}
+
+impl Default for DateInference {
+    fn default() -> Self {
+        DateInference::Rollover
+    }
+}
+
+/// What happens when the timestamps of a log jump in an implausible way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnomalyPolicy {
+    /// Parsing fails at the first anomaly
+    Fail,
+    /// Anomalies are reported and should be shown to the user
+    Warn,
+    /// Anomalies are reported, but expected and need not be shown
+    Accept
+}
+
+impl Default for AnomalyPolicy {
+    fn default() -> Self {
+        AnomalyPolicy::Warn
+    }
+}
+
+/// How lines which cannot be parsed or placed in the log are treated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Parsing fails at the first rejected line
+    Strict,
+    /// Rejected lines are skipped and reported
+    Lenient
+}
+
+impl Default for ParseMode {
+    fn default() -> Self {
+        ParseMode::Strict
+    }
+}
+
+/// Settings of the Chatty parser
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    pub date_inference: DateInference,
+    pub anomaly_policy: AnomalyPolicy,
+    pub mode: ParseMode,
+    /// How bytes which are not valid UTF-8 are decoded
+    pub fallback: Fallback,
+    /// Zone the log was really written in, for logs whose offsets are wrong
+    pub zone: Option<Tz>
+}
+
+#[derive(Debug, Default)]
+pub struct ChattyParser {
+    options: ParserOptions
+}
+
+impl ChattyParser {
+    /// Creates a parser with the default options
+    pub fn new() -> ChattyParser {
+        ChattyParser::default()
+    }
+
+    pub fn with_options(options: ParserOptions) -> ChattyParser {
+        ChattyParser { options }
+    }
+
+    pub fn options(&self) -> &ParserOptions {
+        &self.options
+    }
+
+    /// Parses a log, falling back to what the name of its file tells about it where the log itself
+    /// lacks the start time or channel
+    pub fn parse_with_hints<T: BufRead>(&self, collector: &mut Collector, input: T, hints: &FileHints)
+        -> Result<ParseReport> {
+        self.parse_with_context(collector, input, hints, &mut ParserContext::default())
+    }
+
+    /// Parses a log which continues where `context` left off, e.g. the next file of a day-rotated
+    /// log, and leaves the context at the end of this log.
+    ///
+    /// The time of the context is only used if it does not lie before the date hinted for the file.
+    pub fn parse_with_context<T: BufRead>(&self, collector: &mut Collector, input: T, hints: &FileHints,
+                                          context: &mut ParserContext) -> Result<ParseReport> {
+        let hint_zone = hints.zone.or(self.options.zone);
+        // Without a zone the hinted day is read in the offset the previous file ended in
+        let offset = match hint_zone {
+            Some(_) => None,
+            None => context.log_time.map(|time| *time.offset())
+        };
+        let fallback_start = hints.date.and_then(|date| start_of_day(date, hint_zone, offset));
+        let assumes_utc = hint_zone.is_none() && offset.is_none();
+        let log_time = match (context.log_time, fallback_start) {
+            (Some(time), Some(start)) if time < start => None,
+            (time, _) => time
+        };
+        let mut state = ParseState {
+            log_time,
+            channel: context.channel.clone(),
+            anomaly_policy: self.options.anomaly_policy,
+            mode: self.options.mode,
+            zone: self.options.zone,
+            fallback_channel: hints.channel.clone(),
+            fallback_start,
+            assumes_utc,
+            ..ParseState::default()
+        };
+        let mut report = ParseReport::default();
+        // Lines of the current session, only held back when dates are inferred per session
+        let mut session_lines: Vec<(usize, Line, String)> = Vec::new();
+
+        for (line_num, decoded) in DecodedLines::new(input, self.options.fallback).enumerate() {
+            let decoded = decoded?;
+            if decoded.is_repaired() {
+                report.repairs.push(RepairedLine {
+                    line: line_num + 1,
+                    nul_bytes: decoded.nul_bytes,
+                    invalid_bytes: decoded.invalid_bytes,
+                    fallback: self.options.fallback
+                });
+                // Nothing but padding is left of the line
+                if decoded.text.is_empty() {
+                    continue;
+                }
+            }
+            let raw = decoded.text;
+            // Parse line
+            let parse_result: IResult<&str, Line> = log_line(raw.as_ref());
+
+            // Handle results
+            let line = match (parse_result, self.options.mode) {
+                (IResult::Done(_, line), _) => line,
+                (IResult::Incomplete(_), ParseMode::Strict) => Err(ErrorKind::IncompleteLineError(line_num + 1))?,
+                (IResult::Error(err), ParseMode::Strict) => Err(ErrorKind::ParseError(line_num + 1, err))?,
+                (_, ParseMode::Lenient) => {
+                    state.reject(line_num, RejectReason::Unparsable, raw.clone())?;
+                    continue;
+                }
+            };
+            match self.options.date_inference {
+                DateInference::Rollover => state.handle_line(collector, line_num, line, raw)?,
+                DateInference::Bounded => {
+                    let (begins, ends) = match line {
+                        Line::BeginLog(_) => (true, false),
+                        Line::EndLog(_) => (false, true),
+                        _ => (false, false)
+                    };
+                    if begins {
+                        flush_session(&mut state, collector, &mut session_lines)?;
+                    }
+                    session_lines.push((line_num, line, raw));
+                    if ends {
+                        flush_session(&mut state, collector, &mut session_lines)?;
+                    }
+                }
+            }
+        }
+        flush_session(&mut state, collector, &mut session_lines)?;
+        report.date_warnings = mem::replace(&mut state.date_warnings, Vec::new());
+        report.anomalies = mem::replace(&mut state.anomalies, Vec::new());
+        report.rejects = mem::replace(&mut state.rejects, Vec::new());
+        report.rejects.sort_by_key(|rejected| rejected.line);
+        report.line_counts = mem::replace(&mut state.line_counts, BTreeMap::new());
+        let end = ParserContext { log_time: state.log_time, channel: state.channel.clone() };
+        state.finish(collector)?;
+        *context = end;
+        Ok(report)
+    }
+}
+
+/// Where the parser was in a log, to continue with the next file of the same log
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParserContext {
+    /// Time of the last dated line
+    pub log_time: Option<DateTime<FixedOffset>>,
+    /// The joined channel
+    pub channel: Option<String>
+}
+
+/// Problems noticed in a log which did not keep it from being parsed
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ParseReport {
+    pub date_warnings: Vec<DateWarning>,
+    pub anomalies: Vec<TimestampAnomaly>,
+    pub rejects: Vec<RejectedLine>,
+    pub repairs: Vec<RepairedLine>,
+    /// Number of lines of each type, with all rejected lines counted as `rejected`
+    pub line_counts: BTreeMap<&'static str, usize>
+}
+
+/// A line which was skipped because it could not be parsed or placed in the log
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectedLine {
+    pub line: usize,
+    pub reason: RejectReason,
+    pub text: String
+}
+
+/// A damaged line which could still be read
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RepairedLine {
+    pub line: usize,
+    /// Number of NUL bytes removed
+    pub nul_bytes: usize,
+    /// Number of bytes which were not valid UTF-8
+    pub invalid_bytes: usize,
+    pub fallback: Fallback
+}
+
+impl fmt::Display for RepairedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let decoded = match self.fallback {
+            Fallback::Replace => "replaced",
+            Fallback::Latin1 => "read as Latin-1",
+            Fallback::Cp1252 => "read as CP1252",
+        };
+        write!(f, "repaired line {}: removed {} NUL bytes, {} {} invalid bytes",
+               self.line, self.nul_bytes, decoded, self.invalid_bytes)
+    }
+}
+
+/// Why a line was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// The line is of no known type
+    Unknown,
+    /// A `Log started` or `Log closed` line without a valid timestamp
+    InvalidMarker,
+    /// The timestamp of the line is not a valid date or time
+    InvalidTimestamp,
+    /// The time of the line does not exist in the zone of the log
+    UnresolvableTime,
+    /// The line came before the log was started, so it cannot be dated
+    MissingBeginTimestamp,
+    /// The line belongs to a channel, but no channel was joined yet
+    MissingChannel,
+    /// The time of the line does not fit between the start and close of its session
+    OutsideSession,
+    /// The line could not be parsed at all
+    Unparsable
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match *self {
+            RejectReason::Unknown => "unknown line",
+            RejectReason::InvalidMarker => "invalid log marker",
+            RejectReason::InvalidTimestamp => "invalid timestamp",
+            RejectReason::UnresolvableTime => "time does not exist in the log's zone",
+            RejectReason::MissingBeginTimestamp => "line before the log was started",
+            RejectReason::MissingChannel => "line before a channel was joined",
+            RejectReason::OutsideSession => "time outside of its session",
+            RejectReason::Unparsable => "unparsable line",
+        };
+        f.write_str(reason)
+    }
+}
+
+impl fmt::Display for RejectedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rejected line {} ({}): {}", self.line, self.reason, self.text)
+    }
+}
+
+/// A session whose inferred dates cannot be trusted
+#[derive(Debug, Clone, PartialEq)]
+pub enum DateWarning {
+    /// The date inferred for `line` runs past the time the log was closed at, so the line was
+    /// dated earlier or rejected
+    PastClose {
+        line: usize,
+        inferred: DateTime<FixedOffset>,
+        closed: DateTime<FixedOffset>
+    },
+    /// The log was closed so long after its last line that up to `days` days may be missing from
+    /// the dates inferred between the two lines
+    Ambiguous {
+        from_line: usize,
+        to_line: usize,
+        days: i64
+    },
+    /// The log was not started, so its lines from `line` on were dated from the day its file name
+    /// hints at, which was taken as UTC since no zone is known
+    AssumedUtc {
+        line: usize,
+        date: NaiveDate
+    }
+}
+
+impl fmt::Display for DateWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DateWarning::PastClose { line, inferred, closed } =>
+                write!(f, "line {} was dated {}, after the log was closed at {}", line, inferred, closed),
+            DateWarning::Ambiguous { from_line, to_line, days } =>
+                write!(f, "dates of lines {} to {} may be up to {} days early", from_line, to_line, days),
+            DateWarning::AssumedUtc { line, date } =>
+                write!(f, "lines from {} on were dated from {} in UTC, as the log names no zone", line, date),
+        }
+    }
+}
+
+/// A timestamp which does not follow plausibly from the one before it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimestampAnomaly {
+    pub line: usize,
+    pub previous: DateTime<FixedOffset>,
+    pub new: DateTime<FixedOffset>,
+    pub kind: AnomalyKind
+}
+
+/// The way a timestamp jumped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A full timestamp lies before the previous one, as in logs concatenated out of order
+    Backward,
+    /// A time went back by no more than a clock change and kept the date of the line before, as
+    /// happens when the clock is set back
+    ClockSetBack,
+    /// More than a day passed between two lines of the same session
+    ForwardJump
+}
+
+impl fmt::Display for TimestampAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let jump = match self.kind {
+            AnomalyKind::Backward => "goes back",
+            AnomalyKind::ClockSetBack => "goes back by a little, the clock may have been set back",
+            AnomalyKind::ForwardJump => "jumps forward by more than a day",
+        };
+        write!(f, "time of line {} {} (from {} to {})", self.line, jump, self.previous, self.new)
+    }
+}
+
+/// Largest step back in the time of day which is taken as the clock being set back rather than
+/// as the start of the next day, enough for daylight saving changes
+const CLOCK_SET_BACK_HOURS: i64 = 2;
+
+/// Maximum distance between the chat and moderator log lines of the same action
+const ACTION_MERGE_SECONDS: i64 = 10;
 
 /// Raw time information extracted from a chat message
 #[derive(Debug, Clone, PartialEq)]
@@ -57,6 +414,30 @@ impl MessageTimestamp {
     }
 }
 
+/// Dates a timestamp following `previous` and tells whether the step between them is implausible.
+///
+/// A time-only timestamp which goes back by no more than a clock change keeps the date of
+/// `previous`, any other step back in time of day is taken as the start of the next day.
+fn step(previous: DateTime<FixedOffset>, time: MessageTimestamp)
+    -> Result<(DateTime<FixedOffset>, Option<AnomalyKind>)> {
+    let set_back = previous.time().signed_duration_since(time.time);
+    if time.date.is_none() && set_back > Duration::zero()
+        && set_back <= Duration::hours(CLOCK_SET_BACK_HOURS) {
+        let new = previous.date().and_time(time.time).ok_or(ErrorKind::TimestampError)?;
+        return Ok((new, Some(AnomalyKind::ClockSetBack)));
+    }
+
+    let new = time.into_datetime(previous)?;
+    let kind = if new < previous {
+        Some(AnomalyKind::Backward)
+    } else if new.signed_duration_since(previous) > Duration::days(1) {
+        Some(AnomalyKind::ForwardJump)
+    } else {
+        None
+    };
+    Ok((new, kind))
+}
+
 /// A line as it was parsed from the log
 #[derive(Debug, Clone, PartialEq)]
 enum Line {
@@ -64,6 +445,7 @@ enum Line {
     EndLog(DateTime<FixedOffset>),
     Message {
         time: MessageTimestamp,
+        kind: MessageKind,
         message: String,
         sender: MessageSender
     },
@@ -71,6 +453,14 @@ enum Line {
         time: MessageTimestamp,
         message: String,
     },
+    Moderation {
+        time: MessageTimestamp,
+        action: ModerationAction,
+    },
+    Subscription {
+        time: MessageTimestamp,
+        notice: SubscriptionNotice,
+    },
     JoinedChannel {
         time: MessageTimestamp,
         channel: String,
@@ -79,6 +469,46 @@ enum Line {
     Other(String)
 }
 
+impl Line {
+    /// Name of the type of line, under which it is counted
+    fn name(&self) -> &'static str {
+        match *self {
+            Line::BeginLog(_) => "BeginLog",
+            Line::EndLog(_) => "EndLog",
+            Line::Message { .. } => "Message",
+            Line::SystemMessage { .. } => "SystemMessage",
+            Line::Moderation { .. } => "Moderation",
+            Line::Subscription { .. } => "Subscription",
+            Line::JoinedChannel { .. } => "JoinedChannel",
+            Line::Separator => "Separator",
+            Line::Other(_) => "Other",
+        }
+    }
+
+    /// Timestamp of a line logged within a session
+    fn timestamp(&self) -> Option<&MessageTimestamp> {
+        match *self {
+            Line::Message { ref time, .. } |
+            Line::SystemMessage { ref time, .. } |
+            Line::Moderation { ref time, .. } |
+            Line::Subscription { ref time, .. } |
+            Line::JoinedChannel { ref time, .. } => Some(time),
+            _ => None
+        }
+    }
+
+    fn timestamp_mut(&mut self) -> Option<&mut MessageTimestamp> {
+        match *self {
+            Line::Message { ref mut time, .. } |
+            Line::SystemMessage { ref mut time, .. } |
+            Line::Moderation { ref mut time, .. } |
+            Line::Subscription { ref mut time, .. } |
+            Line::JoinedChannel { ref mut time, .. } => Some(time),
+            _ => None
+        }
+    }
+}
+
 /// Parsed information about the sender of a message
 #[derive(Debug, Clone, PartialEq)]
 struct MessageSender {
@@ -86,8 +516,196 @@ struct MessageSender {
     pub modifiers: Vec<MessageFlag>
 }
 
+/// A timeout or ban as logged in a system line
+#[derive(Debug, Clone, PartialEq)]
+struct ModerationAction {
+    pub kind: ModerationKind,
+    pub target: String,
+    pub duration: Option<u32>,
+    pub reason: Option<String>,
+    pub moderator: Option<String>
+}
+
+/// Recognises the system lines Chatty writes for timeouts and bans.
+///
+/// These come in two forms. `BAN: nick (600s) [reason]` is logged when the messages of a user are
+/// cleared from chat, with duration and reason only present for timeouts and when a reason was
+/// given. `MOD_ACTION: moderator (timeout nick 600 reason)` is only logged if the logging account
+/// can see moderator actions.
+fn parse_moderation_action(text: &str) -> Option<ModerationAction> {
+    if text.starts_with("BAN: ") {
+        parse_ban(&text["BAN: ".len()..])
+    } else if text.starts_with("MOD_ACTION: ") {
+        parse_mod_action(&text["MOD_ACTION: ".len()..])
+    } else {
+        None
+    }
+}
+
+fn parse_ban(text: &str) -> Option<ModerationAction> {
+    let mut parts = text.splitn(2, ' ');
+    let target = parts.next()?;
+    let mut rest = parts.next().unwrap_or("").trim();
+
+    let mut duration = None;
+    if rest.starts_with('(') {
+        let end = rest.find(')')?;
+        duration = Some(rest[1..end].trim_end_matches('s').parse().ok()?);
+        rest = rest[end + 1..].trim_start();
+    }
+    let reason = if rest.is_empty() || rest == "[]" {
+        None
+    } else if rest.starts_with('[') && rest.ends_with(']') {
+        Some(rest[1..rest.len() - 1].to_owned())
+    } else {
+        return None;
+    };
+
+    Some(ModerationAction {
+        kind: if duration.is_some() { ModerationKind::Timeout } else { ModerationKind::Ban },
+        target: target.to_owned(),
+        duration,
+        reason,
+        moderator: None
+    })
+}
+
+fn parse_mod_action(text: &str) -> Option<ModerationAction> {
+    let open = text.find(" (")?;
+    if !text.ends_with(')') {
+        return None;
+    }
+    let moderator = &text[..open];
+    let mut args = text[open + 2..text.len() - 1].split(' ');
+
+    let kind = match args.next()? {
+        "timeout" => ModerationKind::Timeout,
+        "ban" => ModerationKind::Ban,
+        "untimeout" => ModerationKind::Untimeout,
+        "unban" => ModerationKind::Unban,
+        _ => return None
+    };
+    let target = args.next()?;
+    let duration = if kind == ModerationKind::Timeout {
+        Some(args.next()?.parse().ok()?)
+    } else {
+        None
+    };
+    let reason = args.collect::<Vec<_>>().join(" ");
+    let reason = if reason.is_empty() { None } else { Some(reason) };
+
+    Some(ModerationAction {
+        kind,
+        target: target.to_owned(),
+        duration,
+        reason,
+        moderator: Some(moderator.to_owned())
+    })
+}
+
+/// A subscription as announced in a system line
+#[derive(Debug, Clone, PartialEq)]
+struct SubscriptionNotice {
+    pub kind: SubscriptionKind,
+    pub tier: SubscriptionTier,
+    pub subscriber: String,
+    pub recipient: Option<String>,
+    pub months: u32,
+    pub message: Option<String>
+}
+
+/// Recognises the subscription notices Twitch sends, as logged by Chatty.
+///
+/// The notice text is logged as is, for example `JohnDoe subscribed at Tier 1. They've subscribed
+/// for 5 months!` or `JohnDoe gifted a Tier 1 sub to JaneDoe!`, followed by the message the
+/// subscriber attached in brackets.
+fn parse_subscription(text: &str) -> Option<SubscriptionNotice> {
+    let (notice, message) = match text.find(" [") {
+        Some(start) if text.ends_with(']') =>
+            (&text[..start], Some(text[start + 2..text.len() - 1].to_owned())),
+        _ => (text, None)
+    };
+    let mut words = notice.splitn(2, ' ');
+    let subscriber = words.next()?.to_owned();
+    let rest = words.next()?;
+
+    if rest.starts_with("gifted a ") {
+        let end = rest.find(" sub to ")?;
+        let recipient = rest[end + " sub to ".len()..].trim_end_matches(|c| c == '!' || c == '.');
+        return Some(SubscriptionNotice {
+            kind: SubscriptionKind::Gift,
+            tier: parse_tier(&rest["gifted a ".len()..end])?,
+            subscriber,
+            recipient: Some(recipient.to_owned()),
+            months: 1,
+            message
+        });
+    }
+    if !rest.starts_with("subscribed") && !rest.starts_with("just subscribed") {
+        return None;
+    }
+
+    let tier = if rest.contains("Twitch Prime") {
+        SubscriptionTier::Prime
+    } else if let Some(start) = rest.find("Tier ") {
+        parse_tier(rest[start..].get(.."Tier 1".len())?)?
+    } else {
+        SubscriptionTier::Tier1
+    };
+    let months = match rest.find("subscribed for ") {
+        Some(start) => rest[start + "subscribed for ".len()..]
+            .split(' ')
+            .next()?
+            .parse()
+            .ok()?,
+        None => 1
+    };
+
+    Some(SubscriptionNotice {
+        kind: if months > 1 { SubscriptionKind::Resub } else { SubscriptionKind::Sub },
+        tier,
+        subscriber,
+        recipient: None,
+        months,
+        message
+    })
+}
+
+fn parse_tier(text: &str) -> Option<SubscriptionTier> {
+    match text {
+        "Tier 1" => Some(SubscriptionTier::Tier1),
+        "Tier 2" => Some(SubscriptionTier::Tier2),
+        "Tier 3" => Some(SubscriptionTier::Tier3),
+        "Twitch Prime" => Some(SubscriptionTier::Prime),
+        _ => None
+    }
+}
+
+/// Merges two log lines describing the same action, filling in what the first one is missing.
+///
+/// Returns `false` if the actions are not the same and nothing was merged.
+fn merge_actions(first: &mut RawModerationAction, second: &RawModerationAction) -> bool {
+    let same = first.channel == second.channel
+        && first.kind == second.kind
+        && first.target.eq_ignore_ascii_case(&second.target)
+        && first.sent_at.signed_duration_since(second.sent_at).num_seconds().abs() <= ACTION_MERGE_SECONDS;
+    if same {
+        first.duration = first.duration.or(second.duration);
+        if first.reason.is_none() {
+            first.reason = second.reason.clone();
+        }
+        if first.moderator.is_none() {
+            first.moderator = second.moderator.clone();
+        }
+    }
+    same
+}
+
+/// Format of the times in the `Log started` and `Log closed` lines
+const MARKER_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
 fn parse_date_time(data: &str) -> Result<DateTime<FixedOffset>> {
-    let parsed = DateTime::parse_from_str(data, "%Y-%m-%d %H:%M:%S %z")?;
+    let parsed = DateTime::parse_from_str(data, MARKER_TIME_FORMAT)?;
     Ok(parsed)
 }
 
@@ -117,15 +735,15 @@ named!(log_other(&str) -> Line,
 
 named!(log_begin(&str) -> Line,
     map!(
-        preceded!(tag!("# Log started: "), not_line_ending),
-        |time| Line::BeginLog(parse_date_time(time).unwrap())
+        map_res!(preceded!(tag!("# Log started: "), not_line_ending), parse_date_time),
+        Line::BeginLog
     )
 );
 
 named!(log_end(&str) -> Line,
     map!(
-        preceded!(tag!("# Log closed: "), not_line_ending),
-        |time| Line::EndLog(parse_date_time(time).unwrap())
+        map_res!(preceded!(tag!("# Log closed: "), not_line_ending), parse_date_time),
+        Line::EndLog
     )
 );
 
@@ -134,12 +752,21 @@ named!(log_message(&str) -> Line,
         do_parse!(
             time: message_timestamp >>
             space >>
-            sender: message_sender >>
+            sender: alt!(
+                map!(message_sender, |sender| (MessageKind::Chat, sender)) |
+                map!(action_sender, |sender| (MessageKind::Action, sender)) |
+                map!(notice_sender, |sender| (MessageKind::Notice, sender))
+            ) >>
             space >>
             message: not_line_ending >>
             (time, sender, message)
         ),
-        |tuple| Line::Message { time: tuple.0, sender: tuple.1, message: tuple.2.to_string() }
+        |tuple| Line::Message {
+            time: tuple.0,
+            kind: (tuple.1).0,
+            sender: (tuple.1).1,
+            message: tuple.2.to_string()
+        }
     )
 );
 
@@ -165,20 +792,35 @@ named!(log_system_message(&str) -> Line,
             message: not_line_ending >>
             (time, message)
         ),
-        |tuple| Line::SystemMessage { time: tuple.0, message: tuple.1.to_string() }
+        |tuple| system_line(tuple.0, tuple.1)
     )
 );
 
+/// Turns a system line into a typed line if it is one of the known notices
+fn system_line(time: MessageTimestamp, text: &str) -> Line {
+    if let Some(action) = parse_moderation_action(text) {
+        Line::Moderation { time, action }
+    } else if let Some(notice) = parse_subscription(text) {
+        Line::Subscription { time, notice }
+    } else {
+        Line::SystemMessage { time, message: text.to_string() }
+    }
+}
+
+named!(sender_modifiers(&str) -> Vec<MessageFlag>,
+    many0!(alt!(
+        map!(char!('+'), |_| MessageFlag::Prime) |
+        map!(char!('@'), |_| MessageFlag::Moderator) |
+        map!(char!('%'), |_| MessageFlag::Subscriber) |
+        map!(char!('~'), |_| MessageFlag::Broadcaster)
+    ))
+);
+
 named!(message_sender(&str) -> MessageSender,
     map!(
         do_parse!(
             tag!("<") >>
-            modifiers: many0!(alt!(
-                map!(char!('+'), |_| MessageFlag::Prime) |
-                map!(char!('@'), |_| MessageFlag::Moderator) |
-                map!(char!('%'), |_| MessageFlag::Subscriber) |
-                map!(char!('~'), |_| MessageFlag::Broadcaster)
-            )) >>
+            modifiers: sender_modifiers >>
             name: is_not_s!(">") >>
             tag!(">") >>
             (name, modifiers)
@@ -187,12 +829,47 @@ named!(message_sender(&str) -> MessageSender,
     )
 );
 
+// Sender of a `/me` action, which Chatty logs as `* nick text`
+named!(action_sender(&str) -> MessageSender,
+    map!(
+        do_parse!(
+            tag!("* ") >>
+            modifiers: sender_modifiers >>
+            name: is_not_s!(" ") >>
+            (name, modifiers)
+        ),
+        |tuple| MessageSender { name: tuple.0.to_owned(), modifiers: tuple.1 }
+    )
+);
+
+// Sender of a notice, logged as `-nick- text`
+named!(notice_sender(&str) -> MessageSender,
+    map!(
+        delimited!(tag!("-"), is_not_s!("- "), tag!("-")),
+        |name| MessageSender { name: name.to_owned(), modifiers: vec!() }
+    )
+);
+
+/// The date of a timestamp, if its digits form a valid date
+fn timestamp_date(ymd: (&str, &str, &str)) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(i32::from_str(ymd.0).ok()?,
+                            u32::from_str(ymd.1).ok()?,
+                            u32::from_str(ymd.2).ok()?)
+}
+
+/// The time of a timestamp, if its digits form a valid time
+fn timestamp_time(hms: (&str, &str, &str)) -> Option<NaiveTime> {
+    NaiveTime::from_hms_opt(u32::from_str(hms.0).ok()?,
+                            u32::from_str(hms.1).ok()?,
+                            u32::from_str(hms.2).ok()?)
+}
+
 named!(message_timestamp(&str) -> MessageTimestamp,
     map!(
         do_parse!(
             tag!("[") >>
             date: opt!(
-                map!(do_parse!(
+                map_opt!(do_parse!(
                         year: digit >>
                         tag!("-") >>
                         month: digit >>
@@ -200,13 +877,11 @@ named!(message_timestamp(&str) -> MessageTimestamp,
                         day: digit >>
                         (year, month, day)
                     ),
-                    |ymd| NaiveDate::from_ymd(i32::from_str(ymd.0).unwrap(),
-                                              u32::from_str(ymd.1).unwrap(),
-                                              u32::from_str(ymd.2).unwrap())
+                    timestamp_date
                 )
             ) >>
             opt!(tag!(" ")) >>
-            time: map!(
+            time: map_opt!(
                 do_parse!(
                     hour: digit >>
                     tag!(":") >>
@@ -215,9 +890,7 @@ named!(message_timestamp(&str) -> MessageTimestamp,
                     second: digit >>
                     (hour, minute, second)
                 ),
-                |hms| NaiveTime::from_hms(u32::from_str(hms.0).unwrap(),
-                                          u32::from_str(hms.1).unwrap(),
-                                          u32::from_str(hms.2).unwrap())
+                timestamp_time
             ) >>
             tag!("]") >>
             (date, time)
@@ -227,56 +900,444 @@ named!(message_timestamp(&str) -> MessageTimestamp,
 );
 
 impl LogParser for ChattyParser {
-    fn parse<T: BufRead + ? Sized>(collector: &mut Collector, input: T) -> Result<()> where T: Sized {
-        let mut log_time: Option<DateTime<FixedOffset>> = None;
-        let mut channel: Option<String> = None;
+    fn name() -> &'static str {
+        "chatty"
+    }
 
-        for (line_num, line) in input.lines().enumerate() {
-            let line = line?;
-            // Parse line
-            let parse_result: IResult<&str, Line> = log_line(line.as_ref());
+    fn parse<T: BufRead + ? Sized>(&self, collector: &mut Collector, input: T) -> Result<ParseReport>
+        where T: Sized {
+        self.parse_with_hints(collector, input, &FileHints::default())
+    }
+}
 
-            // Handle results
-            match parse_result {
-                IResult::Done(_, line) => {
-                    match line {
-                        Line::BeginLog(time) | Line::EndLog(time) => {
-                            log_time = Some(time);
-                        },
-                        Line::Message { time, message, sender } => {
-                            let channel = channel.as_ref().ok_or(ErrorKind::MissingJoinChannel)?;
-                            let prev_time = log_time.ok_or(ErrorKind::MissingBeginTimestamp)?;
-                            log_time = Some(time.into_datetime(prev_time)?);
-                            collector.add_message(RawMessage {
-                                message,
-                                channel: channel.to_owned(),
-                                nick: sender.name,
-                                sent_at: log_time.unwrap().naive_utc(),
-                                flags: sender.modifiers
-                            })?;
-                        },
-                        Line::SystemMessage { time, .. } => {
-                            let prev_time = log_time.ok_or(ErrorKind::MissingBeginTimestamp)?;
-                            log_time = Some(time.into_datetime(prev_time)?);
-                        },
-                        Line::JoinedChannel { channel: joined, time } => {
-                            let prev_time = log_time.ok_or(ErrorKind::MissingBeginTimestamp)?;
-                            log_time = Some(time.into_datetime(prev_time)?);
-                            channel = Some(joined);
-                        },
-                        Line::Separator => {},
-                        Line::Other(msg) => {
-                            println!("WARN: Unknown message type encountered, ignoring line {}", line_num + 1);
-                            println!("Line was: {}", msg);
-                        }
-                    }
+/// What the parser knows about a log at the current line
+#[derive(Default)]
+struct ParseState {
+    log_time: Option<DateTime<FixedOffset>>,
+    channel: Option<String>,
+    /// Chatty may log the same action twice, so each one is held back until the next arrives
+    pending_action: Option<RawModerationAction>,
+    session: Option<RawSession>,
+    anomaly_policy: AnomalyPolicy,
+    anomalies: Vec<TimestampAnomaly>,
+    mode: ParseMode,
+    rejects: Vec<RejectedLine>,
+    line_counts: BTreeMap<&'static str, usize>,
+    zone: Option<Tz>,
+    /// Channel assumed for lines before any channel was joined, e.g. as hinted by the file name
+    fallback_channel: Option<String>,
+    /// Time assumed for lines before the log was started
+    fallback_start: Option<DateTime<FixedOffset>>,
+    /// Whether `fallback_start` is midnight UTC because no zone is known for the log
+    assumes_utc: bool,
+    date_warnings: Vec<DateWarning>
+}
+
+impl ParseState {
+    /// Reads the wall clock time of a timestamp in the zone given for the log, replacing the offset
+    /// it was logged with
+    fn localize(&self, time: DateTime<FixedOffset>) -> Result<DateTime<FixedOffset>> {
+        match self.zone {
+            Some(zone) => {
+                let local = zone.from_local_datetime(&time.naive_local())
+                    .earliest()
+                    .ok_or(ErrorKind::TimestampError)?;
+                Ok(local.with_timezone(&local.offset().fix()))
+            },
+            None => Ok(time)
+        }
+    }
+
+    /// The joined channel, or the one the log is assumed to be in if none was joined yet
+    fn current_channel(&self) -> Option<String> {
+        self.channel.clone().or_else(|| self.fallback_channel.clone())
+    }
+
+    /// The time assumed for the first line of a log which was not started, warning if its day was
+    /// taken as UTC
+    fn fallback_start(&mut self, line_num: usize) -> Result<DateTime<FixedOffset>> {
+        let start = self.fallback_start.ok_or(ErrorKind::MissingBeginTimestamp)?;
+        if self.assumes_utc {
+            let date = start.naive_utc().date();
+            self.date_warnings.push(DateWarning::AssumedUtc { line: line_num + 1, date });
+        }
+        Ok(start)
+    }
+
+    /// Moves the log time forward to the timestamp of a line, recording any implausible jump
+    fn advance(&mut self, line_num: usize, time: MessageTimestamp) -> Result<()> {
+        let previous = match self.log_time {
+            Some(previous) => previous,
+            None => self.fallback_start(line_num)?
+        };
+        let (new, anomaly) = step(previous, time)?;
+        let new = self.localize(new)?;
+        self.log_time = Some(new);
+        match anomaly {
+            Some(kind) => self.record_anomaly(TimestampAnomaly { line: line_num + 1, previous, new, kind }),
+            None => Ok(())
+        }
+    }
+
+    /// Records a log marker which lies before the time the log was at.
+    ///
+    /// Time passes unlogged between sessions, so markers are only checked for going backwards.
+    fn check_marker(&mut self, line_num: usize, time: DateTime<FixedOffset>) -> Result<()> {
+        match self.log_time {
+            Some(previous) if time < previous => self.record_anomaly(TimestampAnomaly {
+                line: line_num + 1,
+                previous,
+                new: time,
+                kind: AnomalyKind::Backward
+            }),
+            _ => Ok(())
+        }
+    }
+
+    fn record_anomaly(&mut self, anomaly: TimestampAnomaly) -> Result<()> {
+        if self.anomaly_policy == AnomalyPolicy::Fail {
+            Err(ErrorKind::TimestampAnomaly(anomaly.line, anomaly.to_string()))?;
+        }
+        self.anomalies.push(anomaly);
+        Ok(())
+    }
+
+    /// Adds a line to the collector, or rejects it if it is unknown or cannot be placed in the log
+    fn handle_line(&mut self, collector: &mut Collector, line_num: usize, line: Line, raw: String)
+        -> Result<()> {
+        let name = line.name();
+        let unknown = match line {
+            Line::Other(ref text) => Some(unknown_line_reason(text)),
+            _ => None
+        };
+        let reason = match unknown {
+            Some(reason) => reason,
+            None => match self.apply_line(collector, line_num, line) {
+                Ok(()) => {
+                    *self.line_counts.entry(name).or_insert(0) += 1;
+                    return Ok(());
+                },
+                Err(err) => match *err.kind() {
+                    ErrorKind::TimestampError => RejectReason::UnresolvableTime,
+                    ErrorKind::MissingBeginTimestamp => RejectReason::MissingBeginTimestamp,
+                    ErrorKind::MissingJoinChannel => RejectReason::MissingChannel,
+                    _ => return Err(err)
                 }
-                IResult::Incomplete(_) => Err(ErrorKind::IncompleteLineError(line_num + 1))?,
-                IResult::Error(err) => Err(ErrorKind::ParseError(line_num + 1, err))?,
             }
+        };
+        self.reject(line_num, reason, raw)
+    }
+
+    /// Fails in strict mode, otherwise records the line as rejected
+    fn reject(&mut self, line_num: usize, reason: RejectReason, text: String) -> Result<()> {
+        if self.mode == ParseMode::Strict {
+            Err(ErrorKind::RejectedLine(line_num + 1, reason.to_string()))?;
         }
+        *self.line_counts.entry("rejected").or_insert(0) += 1;
+        self.rejects.push(RejectedLine { line: line_num + 1, reason, text });
         Ok(())
     }
+
+    /// Adds the contents of a line to the collector
+    fn apply_line(&mut self, collector: &mut Collector, line_num: usize, line: Line) -> Result<()> {
+        match line {
+            Line::BeginLog(time) => {
+                let time = self.localize(time)?;
+                self.check_marker(line_num, time)?;
+                self.log_time = Some(time);
+                // A session which was never closed ends where the next one begins
+                if let Some(previous) = self.session.take() {
+                    collector.end_session(previous)?;
+                }
+                collector.begin_session(time.naive_utc())?;
+                self.session = Some(RawSession {
+                    channel: self.current_channel(),
+                    started_at: time.naive_utc(),
+                    ended_at: None,
+                    last_seen_at: time.naive_utc(),
+                    message_count: 0
+                });
+            },
+            Line::EndLog(time) => {
+                let time = self.localize(time)?;
+                self.check_marker(line_num, time)?;
+                self.log_time = Some(time);
+                if let Some(mut current) = self.session.take() {
+                    current.ended_at = Some(time.naive_utc());
+                    current.last_seen_at = time.naive_utc();
+                    collector.end_session(current)?;
+                }
+            },
+            Line::Message { time, kind, message, sender } => {
+                let channel = self.current_channel().ok_or(ErrorKind::MissingJoinChannel)?;
+                self.advance(line_num, time)?;
+                // Chatty names the private chat with a user after them, prefixed by $
+                let kind = if kind == MessageKind::Chat && channel.starts_with('$') {
+                    MessageKind::Whisper
+                } else {
+                    kind
+                };
+                collector.add_message(RawMessage {
+                    message,
+                    channel: channel.clone(),
+                    nick: sender.name,
+                    sent_at: self.log_time.unwrap(),
+                    flags: sender.modifiers,
+                    kind
+                })?;
+                if let Some(ref mut current) = self.session {
+                    current.message_count += 1;
+                }
+            },
+            Line::SystemMessage { time, message } => {
+                self.advance(line_num, time)?;
+                collector.add_event(RawEvent {
+                    channel: self.current_channel(),
+                    sent_at: self.log_time.unwrap().naive_utc(),
+                    message
+                })?;
+            },
+            Line::Moderation { time, action } => {
+                let channel = self.current_channel().ok_or(ErrorKind::MissingJoinChannel)?;
+                self.advance(line_num, time)?;
+                let raw_action = RawModerationAction {
+                    channel: channel.clone(),
+                    sent_at: self.log_time.unwrap().naive_utc(),
+                    kind: action.kind,
+                    target: action.target,
+                    duration: action.duration,
+                    reason: action.reason,
+                    moderator: action.moderator
+                };
+                let merged = match self.pending_action {
+                    Some(ref mut previous) => merge_actions(previous, &raw_action),
+                    None => false
+                };
+                if !merged {
+                    if let Some(previous) = mem::replace(&mut self.pending_action, Some(raw_action)) {
+                        collector.add_moderation_action(previous)?;
+                    }
+                }
+            },
+            Line::Subscription { time, notice } => {
+                let channel = self.current_channel().ok_or(ErrorKind::MissingJoinChannel)?;
+                self.advance(line_num, time)?;
+                collector.add_subscription(RawSubscription {
+                    channel: channel.clone(),
+                    sent_at: self.log_time.unwrap().naive_utc(),
+                    kind: notice.kind,
+                    tier: notice.tier,
+                    subscriber: notice.subscriber,
+                    recipient: notice.recipient,
+                    months: notice.months,
+                    message: notice.message
+                })?;
+            },
+            Line::JoinedChannel { channel: joined, time } => {
+                self.advance(line_num, time)?;
+                if let Some(ref mut current) = self.session {
+                    if current.channel.is_none() {
+                        current.channel = Some(joined.clone());
+                    }
+                }
+                self.channel = Some(joined);
+            },
+            Line::Separator | Line::Other(_) => {}
+        }
+        if let (Some(current), Some(time)) = (self.session.as_mut(), self.log_time) {
+            current.last_seen_at = time.naive_utc();
+        }
+        Ok(())
+    }
+
+    /// Hands everything still held back to the collector
+    fn finish(self, collector: &mut Collector) -> Result<()> {
+        if let Some(action) = self.pending_action {
+            collector.add_moderation_action(action)?;
+        }
+        if let Some(current) = self.session {
+            collector.end_session(current)?;
+        }
+        Ok(())
+    }
+}
+
+/// Midnight of a day in `zone`, or at `offset` if no zone is known, or in UTC if neither is
+fn start_of_day(date: NaiveDate, zone: Option<Tz>, offset: Option<FixedOffset>)
+    -> Option<DateTime<FixedOffset>> {
+    let midnight = date.and_hms(0, 0, 0);
+    match zone {
+        Some(zone) => zone.from_local_datetime(&midnight)
+            .earliest()
+            .map(|start| start.with_timezone(&start.offset().fix())),
+        None => offset.unwrap_or(FixedOffset::east(0)).from_local_datetime(&midnight).single()
+    }
+}
+
+/// How the grammar reads a line, as far as repairing logs is concerned
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum LineClass {
+    BeginLog(DateTime<FixedOffset>),
+    EndLog(DateTime<FixedOffset>),
+    /// A chat message, with its time if it could be dated
+    Message(Option<DateTime<FixedOffset>>),
+    /// Any other known line, with its time if it has one and it could be dated
+    Known(Option<DateTime<FixedOffset>>),
+    /// A line the grammar does not recognise
+    Unknown
+}
+
+/// Classifies a line using the log grammar, dating it from the time the log was at before it
+pub(crate) fn classify_line(text: &str, previous: Option<DateTime<FixedOffset>>) -> LineClass {
+    let line = match log_line(text) {
+        IResult::Done(_, line) => line,
+        _ => return LineClass::Unknown
+    };
+    let time = match (line.timestamp(), previous) {
+        (Some(time), Some(previous)) => time.clone().into_datetime(previous).ok(),
+        _ => None
+    };
+    match line {
+        Line::BeginLog(time) => LineClass::BeginLog(time),
+        Line::EndLog(time) => LineClass::EndLog(time),
+        Line::Message { .. } => LineClass::Message(time),
+        Line::Other(_) => LineClass::Unknown,
+        _ => LineClass::Known(time)
+    }
+}
+
+/// Formats a time as in the `Log started` and `Log closed` lines
+pub(crate) fn format_marker_time(time: &DateTime<FixedOffset>) -> String {
+    time.format(MARKER_TIME_FORMAT).to_string()
+}
+
+/// Guesses why a line was not recognised
+fn unknown_line_reason(text: &str) -> RejectReason {
+    if text.starts_with("# Log started:") || text.starts_with("# Log closed:") {
+        RejectReason::InvalidMarker
+    } else if text.starts_with('[') && !message_timestamp(text).is_done() {
+        RejectReason::InvalidTimestamp
+    } else {
+        RejectReason::Unknown
+    }
+}
+
+/// Infers the dates of the buffered lines of a session and then handles them
+fn flush_session(state: &mut ParseState, collector: &mut Collector,
+                 lines: &mut Vec<(usize, Line, String)>) -> Result<()> {
+    let (warnings, datings) = infer_session_dates(state, lines)?;
+    state.date_warnings.extend(warnings);
+    for ((line_num, mut line, raw), dating) in lines.drain(..).zip(datings) {
+        match dating {
+            Dating::Inferred => {},
+            Dating::Redated(date) => if let Some(time) = line.timestamp_mut() {
+                time.date = Some(date);
+            },
+            Dating::OutsideSession => {
+                state.reject(line_num, RejectReason::OutsideSession, raw)?;
+                continue;
+            }
+        }
+        state.handle_line(collector, line_num, line, raw)?;
+    }
+    Ok(())
+}
+
+/// How the date of a buffered line was settled
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dating {
+    /// The line is dated as it would be going forward from the line before
+    Inferred,
+    /// The line is dated on an earlier day to stay within its session
+    Redated(NaiveDate),
+    /// The line fits nowhere between the start and close of its session
+    OutsideSession
+}
+
+/// Infers the dates of the time-only timestamps of a session, bounded by its close marker.
+///
+/// Dates are inferred going forward from the start of the session, like they are when the lines
+/// are handled. If the session was closed, a date running past the close marker is
+/// reported and moved to the latest day that keeps the line between the start and close of the
+/// session, or the line is rejected if there is no such day. A close marker so far after the last
+/// line that whole days may have passed unnoticed in between is reported as well.
+///
+/// # Arguments
+///
+/// * `state` - The state before the session, whose time is used if it does not begin with a marker
+/// * `lines` - The lines of the session with their line numbers and raw text
+fn infer_session_dates(state: &ParseState, lines: &[(usize, Line, String)])
+    -> Result<(Vec<DateWarning>, Vec<Dating>)> {
+    let begin = match lines.first() {
+        Some(&(_, Line::BeginLog(time), _)) => Some(state.localize(time)?),
+        _ => state.log_time.or(state.fallback_start)
+    };
+    let mut current = begin;
+    let first_line = lines.first().map_or(0, |&(line_num, _, _)| line_num);
+    let closed = match lines.last() {
+        Some(&(line_num, Line::EndLog(time), _)) => Some((line_num, state.localize(time)?)),
+        _ => None
+    };
+    let mut warnings = Vec::new();
+    let mut datings = Vec::with_capacity(lines.len());
+
+    for &(line_num, ref line, _) in lines {
+        let (time, prev_time, begin) = match (line.timestamp(), current, begin) {
+            (Some(time), Some(prev_time), Some(begin)) => (time, prev_time, begin),
+            _ => {
+                datings.push(Dating::Inferred);
+                continue;
+            }
+        };
+        // Lines which cannot be dated are rejected when they are handled
+        let inferred = match step(prev_time, time.clone()).and_then(|(time, _)| state.localize(time)) {
+            Ok(inferred) => inferred,
+            Err(_) => {
+                datings.push(Dating::Inferred);
+                continue;
+            }
+        };
+        let closed_at = match closed {
+            Some((_, closed_at)) if inferred > closed_at && time.date.is_none() => closed_at,
+            _ => {
+                current = Some(inferred);
+                datings.push(Dating::Inferred);
+                continue;
+            }
+        };
+
+        warnings.push(DateWarning::PastClose { line: line_num + 1, inferred, closed: closed_at });
+        match latest_before(state, time.time, closed_at) {
+            Some(redated) if redated >= begin => {
+                current = Some(redated);
+                datings.push(Dating::Redated(redated.naive_local().date()));
+            },
+            _ => datings.push(Dating::OutsideSession)
+        }
+    }
+
+    if let (Some((close_line, closed_at)), Some(last_time)) = (closed, current) {
+        let unaccounted = closed_at.signed_duration_since(last_time);
+        if unaccounted >= Duration::days(1) {
+            warnings.push(DateWarning::Ambiguous {
+                from_line: first_line + 1,
+                to_line: close_line + 1,
+                days: unaccounted.num_days()
+            });
+        }
+    }
+    Ok((warnings, datings))
+}
+
+/// The latest time of day `time` which is not after `limit`, localized like the times of lines
+fn latest_before(state: &ParseState, time: NaiveTime, limit: DateTime<FixedOffset>)
+    -> Option<DateTime<FixedOffset>> {
+    let same_day = state.localize(limit.date().and_time(time)?).ok()?;
+    if same_day <= limit {
+        Some(same_day)
+    } else {
+        state.localize(limit.date().pred().and_time(time)?).ok()
+    }
 }
 
 #[cfg(test)]
@@ -295,8 +1356,540 @@ mod test {
 [01:10:00] <@+JohnDoe> test message 2
 [02:10:00] <@+JohnDoe> test message 3
 # Log closed: 2017-10-08 15:19:46 +0200";
-        ChattyParser::parse(&mut collector, BufReader::new(text.as_bytes()))
+        ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+    }
+
+    #[test]
+    fn collect_events() {
+        let mut collector = VecCollector::new();
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:41:00] Joining #_cerebot..
+[23:45:00] You have joined #_cerebot
+[23:50:00] JaneDoe is now hosting you.
+[23:51:00] <JaneDoe> test message
+";
+        ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        assert_eq!(collector.messages.len(), 1);
+        assert_eq!(collector.events.len(), 2);
+        assert_eq!(collector.events[0].channel, None);
+        assert_eq!(collector.events[0].message, "Joining #_cerebot..");
+        assert_eq!(collector.events[1].channel, Some("#_cerebot".to_owned()));
+        assert_eq!(collector.events[1].message, "JaneDoe is now hosting you.");
+        assert_eq!(collector.events[1].sent_at, NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 50, 0));
+    }
+
+    #[test]
+    fn collect_moderation_actions() {
+        let mut collector = VecCollector::new();
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] BAN: johndoe (600s)
+[23:50:01] MOD_ACTION: JaneDoe (timeout johndoe 600 spam)
+[23:55:00] BAN: janedoe
+";
+        ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        assert_eq!(collector.moderation_actions, vec!(
+            RawModerationAction {
+                channel: "#_cerebot".to_owned(),
+                sent_at: NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 50, 0),
+                kind: ModerationKind::Timeout,
+                target: "johndoe".to_owned(),
+                duration: Some(600),
+                reason: Some("spam".to_owned()),
+                moderator: Some("JaneDoe".to_owned())
+            },
+            RawModerationAction {
+                channel: "#_cerebot".to_owned(),
+                sent_at: NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 55, 0),
+                kind: ModerationKind::Ban,
+                target: "janedoe".to_owned(),
+                duration: None,
+                reason: None,
+                moderator: None
+            }
+        ));
+        assert!(collector.events.is_empty());
+    }
+
+    #[test]
+    fn parse_moderation_lines() {
+        assert_eq!(parse_moderation_action("BAN: johndoe (600s) [no links]"), Some(ModerationAction {
+            kind: ModerationKind::Timeout,
+            target: "johndoe".to_owned(),
+            duration: Some(600),
+            reason: Some("no links".to_owned()),
+            moderator: None
+        }));
+        assert_eq!(parse_moderation_action("BAN: johndoe [spam bot]"), Some(ModerationAction {
+            kind: ModerationKind::Ban,
+            target: "johndoe".to_owned(),
+            duration: None,
+            reason: Some("spam bot".to_owned()),
+            moderator: None
+        }));
+        assert_eq!(parse_moderation_action("MOD_ACTION: janedoe (unban johndoe)"), Some(ModerationAction {
+            kind: ModerationKind::Unban,
+            target: "johndoe".to_owned(),
+            duration: None,
+            reason: None,
+            moderator: Some("janedoe".to_owned())
+        }));
+        assert_eq!(parse_moderation_action("MOD_ACTION: janedoe (slow 30)"), None);
+        assert_eq!(parse_moderation_action("BAN: johndoe was here"), None);
+    }
+
+    #[test]
+    fn parse_subscription_notices() {
+        assert_eq!(parse_subscription("JohnDoe subscribed at Tier 1."), Some(SubscriptionNotice {
+            kind: SubscriptionKind::Sub,
+            tier: SubscriptionTier::Tier1,
+            subscriber: "JohnDoe".to_owned(),
+            recipient: None,
+            months: 1,
+            message: None
+        }));
+        assert_eq!(parse_subscription("JohnDoe subscribed with Twitch Prime. They've subscribed for \
+                                       5 months! [hello chat]"),
+                   Some(SubscriptionNotice {
+                       kind: SubscriptionKind::Resub,
+                       tier: SubscriptionTier::Prime,
+                       subscriber: "JohnDoe".to_owned(),
+                       recipient: None,
+                       months: 5,
+                       message: Some("hello chat".to_owned())
+                   }));
+        assert_eq!(parse_subscription("JohnDoe gifted a Tier 3 sub to JaneDoe!"), Some(SubscriptionNotice {
+            kind: SubscriptionKind::Gift,
+            tier: SubscriptionTier::Tier3,
+            subscriber: "JohnDoe".to_owned(),
+            recipient: Some("JaneDoe".to_owned()),
+            months: 1,
+            message: None
+        }));
+        assert_eq!(parse_subscription("JohnDoe is now hosting you."), None);
+    }
+
+    #[test]
+    fn parse_message_kinds() {
+        let mut collector = VecCollector::new();
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:46:00] <JohnDoe> hello
+[23:47:00] * @JohnDoe waves
+[23:48:00] -jtv- This room is now in slow mode.
+[23:49:00] You have joined $janedoe
+[23:50:00] <JaneDoe> psst
+";
+        ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let kinds: Vec<_> = collector.messages.iter().map(|m| m.kind).collect();
+        assert_eq!(kinds, vec!(MessageKind::Chat, MessageKind::Action, MessageKind::Notice,
+                               MessageKind::Whisper));
+        assert_eq!(collector.messages[1].nick, "JohnDoe");
+        assert_eq!(collector.messages[1].message, "waves");
+        assert_eq!(collector.messages[1].flags, vec!(MessageFlag::Moderator));
+        assert_eq!(collector.messages[2].nick, "jtv");
+    }
+
+    #[test]
+    fn collect_sessions() {
+        let mut collector = VecCollector::new();
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:46:00] <JohnDoe> hello
+[23:47:00] <JohnDoe> bye
+# Log closed: 2017-10-05 23:50:00 +0200
+# Log started: 2017-10-06 10:00:00 +0200
+[10:00:05] You have joined #_cerebot
+[10:05:00] <JohnDoe> hello again
+# Log started: 2017-10-06 12:00:00 +0200
+";
+        ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let cerebot = Some("#_cerebot".to_owned());
+        assert_eq!(collector.sessions, vec!(
+            RawSession {
+                channel: cerebot.clone(),
+                started_at: NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 40, 0),
+                ended_at: Some(NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 50, 0)),
+                last_seen_at: NaiveDate::from_ymd(2017, 10, 5).and_hms(21, 50, 0),
+                message_count: 2
+            },
+            RawSession {
+                channel: cerebot.clone(),
+                started_at: NaiveDate::from_ymd(2017, 10, 6).and_hms(8, 0, 0),
+                ended_at: None,
+                last_seen_at: NaiveDate::from_ymd(2017, 10, 6).and_hms(8, 5, 0),
+                message_count: 1
+            },
+            RawSession {
+                channel: cerebot,
+                started_at: NaiveDate::from_ymd(2017, 10, 6).and_hms(10, 0, 0),
+                ended_at: None,
+                last_seen_at: NaiveDate::from_ymd(2017, 10, 6).and_hms(10, 0, 0),
+                message_count: 0
+            }
+        ));
+    }
+
+    #[test]
+    fn bounded_dates_match_rollover() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[00:10:00] <JohnDoe> test message 1
+[01:10:00] <JohnDoe> test message 2
+# Log closed: 2017-10-06 02:00:00 +0200";
+        let mut rollover = VecCollector::new();
+        ChattyParser::new().parse(&mut rollover, BufReader::new(text.as_bytes()))
             .unwrap();
+        let mut bounded = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            date_inference: DateInference::Bounded,
+            ..ParserOptions::default()
+        });
+        let report = parser.parse(&mut bounded, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        assert!(report.date_warnings.is_empty());
+        assert!(report.anomalies.is_empty());
+        let dates = |c: &VecCollector| c.messages.iter().map(|m| m.sent_at).collect::<Vec<_>>();
+        assert_eq!(dates(&bounded), dates(&rollover));
+        assert_eq!(bounded.messages[0].sent_at.naive_utc(), NaiveDate::from_ymd(2017, 10, 5).and_hms(22, 10, 0));
+    }
+
+    #[test]
+    fn bounded_dates_report_warnings() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> test message 1
+[20:10:00] <JohnDoe> test message 2
+# Log closed: 2017-10-05 23:55:00 +0200
+# Log started: 2017-10-06 10:00:00 +0200
+[10:05:00] <JohnDoe> test message 3
+[07:00:00] <JohnDoe> test message 4
+# Log closed: 2017-10-09 12:00:00 +0200";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            date_inference: DateInference::Bounded,
+            mode: ParseMode::Lenient,
+            ..ParserOptions::default()
+        });
+        let report = parser.parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let offset = FixedOffset::east(2 * 3600);
+        assert_eq!(report.date_warnings, vec!(
+            DateWarning::PastClose {
+                line: 4,
+                inferred: offset.ymd(2017, 10, 6).and_hms(20, 10, 0),
+                closed: offset.ymd(2017, 10, 5).and_hms(23, 55, 0)
+            },
+            DateWarning::Ambiguous { from_line: 6, to_line: 9, days: 2 }
+        ));
+        assert_eq!(report.rejects, vec!(RejectedLine {
+            line: 4,
+            reason: RejectReason::OutsideSession,
+            text: "[20:10:00] <JohnDoe> test message 2".to_owned()
+        }));
+        let messages = collector.messages.iter().map(|m| m.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, vec!("test message 1", "test message 3", "test message 4"));
+    }
+
+    #[test]
+    fn bounded_dates_stay_before_close() {
+        let text: &str =
+"# Log started: 2017-10-05 20:00:00 +0200
+[20:05:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> test message 1
+[10:10:00] <JohnDoe> test message 2
+[10:20:00] <JohnDoe> test message 3
+[23:40:00] <JohnDoe> test message 4
+# Log closed: 2017-10-06 23:30:00 +0200";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            date_inference: DateInference::Bounded,
+            zone: Some("Europe/Berlin".parse().unwrap()),
+            ..ParserOptions::default()
+        });
+        let report = parser.parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let offset = FixedOffset::east(2 * 3600);
+        let closed = offset.ymd(2017, 10, 6).and_hms(23, 30, 0);
+        assert_eq!(report.date_warnings, vec!(
+            DateWarning::PastClose { line: 6, inferred: offset.ymd(2017, 10, 6).and_hms(23, 40, 0), closed }
+        ));
+        assert!(report.rejects.is_empty());
+        assert_eq!(report.anomalies.iter().map(|a| (a.line, a.kind)).collect::<Vec<_>>(),
+                   vec!((6, AnomalyKind::Backward)));
+        let times = collector.messages.iter().map(|m| m.sent_at).collect::<Vec<_>>();
+        assert_eq!(times, vec!(
+            offset.ymd(2017, 10, 5).and_hms(23, 50, 0),
+            offset.ymd(2017, 10, 6).and_hms(10, 10, 0),
+            offset.ymd(2017, 10, 6).and_hms(10, 20, 0),
+            offset.ymd(2017, 10, 5).and_hms(23, 40, 0)
+        ));
+    }
+
+    #[test]
+    fn overnight_gap_is_no_anomaly() {
+        let text: &str =
+"# Log started: 2017-10-05 21:40:00 +0200
+[21:45:00] You have joined #_cerebot
+[22:00:00] <JohnDoe> test message 1
+[11:00:00] <JohnDoe> test message 2";
+        let mut collector = VecCollector::new();
+        let report = ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        assert!(report.anomalies.is_empty());
+        assert_eq!(collector.messages[1].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(11, 0, 0));
+    }
+
+    #[test]
+    fn report_timestamp_anomalies() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[00:10:00] <JohnDoe> test message 1
+[10:05:00] <JohnDoe> test message 2
+[09:05:00] <JohnDoe> test message 3
+[2017-10-09 12:00:00] <JohnDoe> test message 4
+[2017-10-08 12:00:00] <JohnDoe> test message 5
+# Log closed: 2017-10-08 12:30:00 +0200";
+        let mut collector = VecCollector::new();
+        let report = ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let offset = FixedOffset::east(2 * 3600);
+        assert_eq!(report.anomalies, vec!(
+            TimestampAnomaly {
+                line: 5,
+                previous: offset.ymd(2017, 10, 6).and_hms(10, 5, 0),
+                new: offset.ymd(2017, 10, 6).and_hms(9, 5, 0),
+                kind: AnomalyKind::ClockSetBack
+            },
+            TimestampAnomaly {
+                line: 6,
+                previous: offset.ymd(2017, 10, 6).and_hms(9, 5, 0),
+                new: offset.ymd(2017, 10, 9).and_hms(12, 0, 0),
+                kind: AnomalyKind::ForwardJump
+            },
+            TimestampAnomaly {
+                line: 7,
+                previous: offset.ymd(2017, 10, 9).and_hms(12, 0, 0),
+                new: offset.ymd(2017, 10, 8).and_hms(12, 0, 0),
+                kind: AnomalyKind::Backward
+            }
+        ));
+        assert_eq!(collector.messages.len(), 5);
+    }
+
+    #[test]
+    fn fail_on_timestamp_anomaly() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> test message 1
+# Log started: 2017-10-05 20:00:00 +0200
+[20:05:00] <JohnDoe> test message 2";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            anomaly_policy: AnomalyPolicy::Fail,
+            ..ParserOptions::default()
+        });
+        let result = parser.parse(&mut collector, BufReader::new(text.as_bytes()));
+
+        match result.unwrap_err().kind() {
+            &ErrorKind::TimestampAnomaly(line, _) => assert_eq!(line, 4),
+            kind => panic!("unexpected error {:?}", kind)
+        }
+    }
+
+    #[test]
+    fn override_log_zone() {
+        let text: &str =
+"# Log started: 2017-10-28 23:00:00 +0000
+[23:05:00] You have joined #_cerebot
+[23:30:00] <JohnDoe> test message 1
+[12:00:00] <JohnDoe> test message 2";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            zone: Some("Europe/Berlin".parse().unwrap()),
+            ..ParserOptions::default()
+        });
+        parser.parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        assert_eq!(collector.messages[0].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 28).and_hms(23, 30, 0));
+        assert_eq!(collector.messages[1].sent_at, FixedOffset::east(3600).ymd(2017, 10, 29).and_hms(12, 0, 0));
+    }
+
+    #[test]
+    fn reject_broken_lines() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:41:00] <JohnDoe> too early
+[23:45:00] You have joined #_cerebot
+[2017-13-40 23:50:00] <JohnDoe> impossible date
+# Log closed: 2017-99-99 00:00:00
+[23:55:00] <JohnDoe> test message
+something else";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            mode: ParseMode::Lenient,
+            ..ParserOptions::default()
+        });
+        let report = parser.parse(&mut collector, BufReader::new(text.as_bytes()))
+            .unwrap();
+
+        let rejected = |line: usize, reason: RejectReason, text: &str| RejectedLine {
+            line, reason, text: text.to_owned()
+        };
+        assert_eq!(report.rejects, vec!(
+            rejected(2, RejectReason::MissingChannel, "[23:41:00] <JohnDoe> too early"),
+            rejected(4, RejectReason::InvalidTimestamp, "[2017-13-40 23:50:00] <JohnDoe> impossible date"),
+            rejected(5, RejectReason::InvalidMarker, "# Log closed: 2017-99-99 00:00:00"),
+            rejected(7, RejectReason::Unknown, "something else")
+        ));
+        assert_eq!(report.line_counts.get("Message"), Some(&1));
+        assert_eq!(report.line_counts.get("rejected"), Some(&4));
+        assert_eq!(report.line_counts.values().sum::<usize>(), 7);
+        assert_eq!(collector.messages.len(), 1);
+    }
+
+    #[test]
+    fn repair_damaged_lines() {
+        let text: &[u8] =
+b"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> caf\xe9
+\x00\x00\x00\x00
+[23:55:00] <JohnDoe> test\x00\x00 message";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::with_options(ParserOptions {
+            fallback: Fallback::Latin1,
+            ..ParserOptions::default()
+        });
+        let report = parser.parse(&mut collector, BufReader::new(text))
+            .unwrap();
+
+        assert_eq!(report.repairs, vec!(
+            RepairedLine { line: 3, nul_bytes: 0, invalid_bytes: 1, fallback: Fallback::Latin1 },
+            RepairedLine { line: 4, nul_bytes: 4, invalid_bytes: 0, fallback: Fallback::Latin1 },
+            RepairedLine { line: 5, nul_bytes: 2, invalid_bytes: 0, fallback: Fallback::Latin1 }
+        ));
+        assert!(report.rejects.is_empty());
+        let messages = collector.messages.iter().map(|m| m.message.as_str()).collect::<Vec<_>>();
+        assert_eq!(messages, vec!("café", "test message"));
+    }
+
+    #[test]
+    fn fall_back_to_file_hints() {
+        let text: &str =
+"[23:50:00] <JohnDoe> test message 1
+[00:10:00] <JohnDoe> test message 2";
+        let hints = FileHints {
+            channel: Some("#_cerebot".to_owned()),
+            date: Some(NaiveDate::from_ymd(2017, 10, 5)),
+            zone: Some("Europe/Berlin".parse().unwrap())
+        };
+        let mut collector = VecCollector::new();
+        let report = ChattyParser::new()
+            .parse_with_hints(&mut collector, BufReader::new(text.as_bytes()), &hints)
+            .unwrap();
+
+        assert!(report.rejects.is_empty());
+        assert_eq!(collector.messages.len(), 2);
+        assert_eq!(collector.messages[0].channel, "#_cerebot");
+        assert_eq!(collector.messages[0].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 5).and_hms(23, 50, 0));
+        assert_eq!(collector.messages[1].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(0, 10, 0));
+    }
+
+    #[test]
+    fn warn_about_hinted_day_in_utc() {
+        let text: &str =
+"[23:50:00] <JohnDoe> test message 1
+[00:10:00] <JohnDoe> test message 2";
+        let hints = FileHints {
+            channel: Some("#_cerebot".to_owned()),
+            date: Some(NaiveDate::from_ymd(2017, 10, 5)),
+            zone: None
+        };
+        let mut collector = VecCollector::new();
+        let report = ChattyParser::new()
+            .parse_with_hints(&mut collector, BufReader::new(text.as_bytes()), &hints)
+            .unwrap();
+
+        assert_eq!(report.date_warnings, vec!(
+            DateWarning::AssumedUtc { line: 1, date: NaiveDate::from_ymd(2017, 10, 5) }
+        ));
+        assert_eq!(collector.messages[0].sent_at, FixedOffset::east(0).ymd(2017, 10, 5).and_hms(23, 50, 0));
+    }
+
+    #[test]
+    fn continue_in_next_file() {
+        let first: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> test message 1";
+        let second: &str =
+"[23:55:00] <JohnDoe> test message 2
+[00:10:00] <JohnDoe> test message 3";
+        let mut collector = VecCollector::new();
+        let parser = ChattyParser::new();
+        let mut context = ParserContext::default();
+        parser.parse_with_context(&mut collector, BufReader::new(first.as_bytes()), &FileHints::default(),
+                                  &mut context)
+            .unwrap();
+        assert_eq!(context, ParserContext {
+            log_time: Some(FixedOffset::east(2 * 3600).ymd(2017, 10, 5).and_hms(23, 50, 0)),
+            channel: Some("#_cerebot".to_owned())
+        });
+        let report = parser.parse_with_context(&mut collector, BufReader::new(second.as_bytes()),
+                                               &FileHints::default(), &mut context)
+            .unwrap();
+
+        assert!(report.rejects.is_empty());
+        assert_eq!(collector.messages[2].channel, "#_cerebot");
+        assert_eq!(collector.messages[2].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 6).and_hms(0, 10, 0));
+        assert_eq!(context.log_time, Some(collector.messages[2].sent_at));
+
+        // A context from before the hinted day is left behind, but its offset is kept
+        let hints = FileHints { date: Some(NaiveDate::from_ymd(2017, 10, 8)), ..FileHints::default() };
+        let report = parser.parse_with_context(&mut collector, BufReader::new(second.as_bytes()), &hints,
+                                               &mut context)
+            .unwrap();
+        assert!(report.date_warnings.is_empty());
+        assert_eq!(collector.messages[3].sent_at, FixedOffset::east(2 * 3600).ymd(2017, 10, 8).and_hms(23, 55, 0));
+    }
+
+    #[test]
+    fn strict_mode_fails_on_rejected_line() {
+        let text: &str =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[2017-13-40 23:50:00] <JohnDoe> impossible date";
+        let mut collector = VecCollector::new();
+        let result = ChattyParser::new().parse(&mut collector, BufReader::new(text.as_bytes()));
+
+        match result.unwrap_err().kind() {
+            &ErrorKind::RejectedLine(line, _) => assert_eq!(line, 3),
+            kind => panic!("unexpected error {:?}", kind)
+        }
     }
 
     #[test]
@@ -405,6 +1998,7 @@ mod test {
                 time: NaiveTime::from_hms(22, 5, 44),
                 date: Some(NaiveDate::from_ymd(2017, 10, 8)),
             },
+            kind: MessageKind::Chat,
             message: "this is a test".to_owned(),
             sender: MessageSender {
                 name: "JohnDoe".to_owned(), modifiers: vec!(MessageFlag::Prime)
@@ -430,6 +2024,7 @@ mod test {
                 time: NaiveTime::from_hms(22, 5, 44),
                 date: Some(NaiveDate::from_ymd(2017, 10, 8)),
             },
+            kind: MessageKind::Chat,
             message: "this is a test".to_owned(),
             sender: MessageSender {
                 name: "JohnDoe".to_owned(), modifiers: vec!(MessageFlag::Prime)
@@ -0,0 +1,334 @@
+use chrono::{DateTime, FixedOffset};
+use errors::Result;
+use input::{DecodedLines, Fallback};
+use parser::{classify_line, format_marker_time, LineClass};
+
+use std::fmt;
+use std::io::{BufRead, Write};
+
+static LOG_STARTED: &str = "# Log started: ";
+
+/// Damage left in a log by a crash of the logging client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// NUL padding or bytes which are not valid UTF-8
+    Bytes,
+    /// A line cut off mid-write, with the next `Log started` line glued to it
+    GluedLine,
+    /// A `Log started` line directly followed by another one
+    DuplicateHeader,
+    /// A session with messages that was never closed
+    MissingClose,
+    /// Unrecognised lines at the end of the log
+    TrailingGarbage
+}
+
+impl fmt::Display for Damage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let damage = match *self {
+            Damage::Bytes => "damaged bytes",
+            Damage::GluedLine => "truncated line glued to a log start",
+            Damage::DuplicateHeader => "duplicate log start",
+            Damage::MissingClose => "missing log close",
+            Damage::TrailingGarbage => "trailing garbage",
+        };
+        f.write_str(damage)
+    }
+}
+
+/// A change made to a log, in the manner of a diff hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// Line of the original log the change was made at
+    pub line: usize,
+    pub damage: Damage,
+    pub removed: Vec<String>,
+    pub added: Vec<String>
+}
+
+/// A log with its damage repaired
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairedLog {
+    pub lines: Vec<String>,
+    pub fixes: Vec<Fix>
+}
+
+impl RepairedLog {
+    /// Writes the repaired lines
+    pub fn write_lines<W: Write>(&self, mut output: W) -> Result<()> {
+        for line in &self.lines {
+            writeln!(output, "{}", line)?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    /// Writes the fixes as a diff-style report
+    pub fn write_report<W: Write>(&self, mut output: W) -> Result<()> {
+        for fix in &self.fixes {
+            writeln!(output, "@@ line {}: {}", fix.line, fix.damage)?;
+            for line in &fix.removed {
+                writeln!(output, "-{}", line)?;
+            }
+            for line in &fix.added {
+                writeln!(output, "+{}", line)?;
+            }
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+/// The session the log is in while it is being repaired
+#[derive(Default)]
+struct Repairer {
+    /// Kept lines with the line of the original log they came from
+    lines: Vec<(usize, String)>,
+    fixes: Vec<Fix>,
+    log_time: Option<DateTime<FixedOffset>>,
+    /// Index of the `Log started` line of the open session among the kept lines
+    session_start: Option<usize>,
+    /// Whether the open session contains any lines besides its start
+    session_used: bool
+}
+
+impl Repairer {
+    fn add_line(&mut self, line_num: usize, text: String) {
+        if let Some(start) = text.find(LOG_STARTED) {
+            // Only a valid header counts, chat may quote the marker
+            let glued = start > 0 && match classify_line(&text[start..], self.log_time) {
+                LineClass::BeginLog(_) => true,
+                _ => false
+            };
+            if glued {
+                self.split_glued_line(line_num, text, start);
+                return;
+            }
+        }
+
+        match classify_line(&text, self.log_time) {
+            LineClass::BeginLog(time) => {
+                if !self.session_used {
+                    if let Some(start) = self.session_start.take() {
+                        let (header_line, header) = self.lines.remove(start);
+                        self.fixes.push(Fix {
+                            line: header_line,
+                            damage: Damage::DuplicateHeader,
+                            removed: vec!(header),
+                            added: vec!()
+                        });
+                    }
+                }
+                self.close_session(line_num);
+                self.session_start = Some(self.lines.len());
+                self.session_used = false;
+                self.log_time = Some(time);
+            },
+            LineClass::EndLog(time) => {
+                self.session_start = None;
+                self.log_time = Some(time);
+            },
+            LineClass::Message(time) | LineClass::Known(time) => {
+                self.session_used = true;
+                if time.is_some() {
+                    self.log_time = time;
+                }
+            },
+            LineClass::Unknown => self.session_used = true
+        }
+        self.lines.push((line_num, text));
+    }
+
+    /// Separates a line cut off mid-write from the `Log started` line written after the restart.
+    ///
+    /// The cut off part is kept if it still reads as a chat message.
+    fn split_glued_line(&mut self, line_num: usize, text: String, start: usize) {
+        let (fragment, header) = {
+            let (fragment, header) = text.split_at(start);
+            (fragment.to_owned(), header.to_owned())
+        };
+        let keep_fragment = match classify_line(&fragment, self.log_time) {
+            LineClass::Message(_) => true,
+            _ => false
+        };
+        let mut added = vec!();
+        if keep_fragment {
+            added.push(fragment.clone());
+        }
+        added.push(header.clone());
+        self.fixes.push(Fix { line: line_num, damage: Damage::GluedLine, removed: vec!(text), added });
+
+        if keep_fragment {
+            self.add_line(line_num, fragment);
+        }
+        self.add_line(line_num, header);
+    }
+
+    /// Adds the missing `Log closed` line to an open session which logged anything
+    fn close_session(&mut self, line_num: usize) {
+        if self.session_start.take().is_none() || !self.session_used {
+            return;
+        }
+        if let Some(time) = self.log_time {
+            let close = format!("# Log closed: {}", format_marker_time(&time));
+            self.fixes.push(Fix {
+                line: line_num,
+                damage: Damage::MissingClose,
+                removed: vec!(),
+                added: vec!(close.clone())
+            });
+            self.lines.push((line_num, close));
+        }
+    }
+
+    /// Drops unrecognised lines at the end and closes the last session
+    fn finish(mut self, last_line: usize) -> RepairedLog {
+        let mut garbage = Vec::new();
+        while let Some((line_num, text)) = self.lines.pop() {
+            if classify_line(&text, self.log_time) == LineClass::Unknown {
+                garbage.push((line_num, text));
+            } else {
+                self.lines.push((line_num, text));
+                break;
+            }
+        }
+        if let Some(&(first_line, _)) = garbage.last() {
+            garbage.reverse();
+            self.fixes.push(Fix {
+                line: first_line,
+                damage: Damage::TrailingGarbage,
+                removed: garbage.into_iter().map(|(_, text)| text).collect(),
+                added: vec!()
+            });
+            // The session only counts as used if anything but garbage was logged in it
+            self.session_used = match self.session_start {
+                Some(start) => self.lines.len() > start + 1,
+                None => false
+            };
+        }
+        self.close_session(last_line + 1);
+
+        RepairedLog {
+            lines: self.lines.into_iter().map(|(_, text)| text).collect(),
+            fixes: self.fixes
+        }
+    }
+}
+
+/// Repairs the damage a crash of the logging client leaves in a Chatty log.
+///
+/// Lines are judged by the same grammar the parser uses. Damaged bytes are decoded with
+/// `fallback`, lines cut off and glued to the next `Log started` line are split, empty sessions
+/// directly followed by another start are removed, sessions that were never closed are closed at
+/// the time of their last line and unrecognised lines at the end of the log are dropped.
+pub fn repair<R: BufRead>(input: R, fallback: Fallback) -> Result<RepairedLog> {
+    let mut repairer = Repairer::default();
+    let mut last_line = 0;
+
+    for (line_num, decoded) in DecodedLines::new(input, fallback).enumerate() {
+        let decoded = decoded?;
+        last_line = line_num + 1;
+        if decoded.is_repaired() {
+            repairer.fixes.push(Fix {
+                line: line_num + 1,
+                damage: Damage::Bytes,
+                removed: vec!(),
+                added: if decoded.text.is_empty() { vec!() } else { vec!(decoded.text.clone()) }
+            });
+            // Nothing but padding is left of the line
+            if decoded.text.is_empty() {
+                continue;
+            }
+        }
+        repairer.add_line(line_num + 1, decoded.text);
+    }
+    Ok(repairer.finish(last_line))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn repair_text(text: &[u8]) -> RepairedLog {
+        repair(text, Fallback::Replace).unwrap()
+    }
+
+    #[test]
+    fn split_glued_line() {
+        let repaired = repair_text(
+b"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> test mess# Log started: 2017-10-06 10:00:00 +0200
+[10:00:01] You have joined #_cerebot
+# Log closed: 2017-10-06 11:00:00 +0200");
+
+        assert_eq!(repaired.lines, vec!(
+            "# Log started: 2017-10-05 23:40:00 +0200",
+            "[23:45:00] You have joined #_cerebot",
+            "[23:50:00] <JohnDoe> test mess",
+            "# Log closed: 2017-10-05 23:50:00 +0200",
+            "# Log started: 2017-10-06 10:00:00 +0200",
+            "[10:00:01] You have joined #_cerebot",
+            "# Log closed: 2017-10-06 11:00:00 +0200"
+        ));
+        let damage = repaired.fixes.iter().map(|fix| fix.damage).collect::<Vec<_>>();
+        assert_eq!(damage, vec!(Damage::GluedLine, Damage::MissingClose));
+    }
+
+    #[test]
+    fn keep_quoted_header() {
+        let text =
+"# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[23:50:00] <JohnDoe> paste: # Log started: foo
+# Log closed: 2017-10-05 23:55:00 +0200";
+        let repaired = repair_text(text.as_bytes());
+
+        assert_eq!(repaired.lines, text.lines().collect::<Vec<_>>());
+        assert!(repaired.fixes.is_empty());
+    }
+
+    #[test]
+    fn remove_duplicate_headers_and_garbage() {
+        let repaired = repair_text(
+b"# Log started: 2017-10-05 23:40:00 +0200
+# Log started: 2017-10-05 23:40:00 +0200
+[23:45:00] You have joined #_cerebot
+[00:10:00] <JohnDoe> test message
+\x00\x00\x00\x00
+\x13\x37garbage");
+
+        assert_eq!(repaired.lines, vec!(
+            "# Log started: 2017-10-05 23:40:00 +0200",
+            "[23:45:00] You have joined #_cerebot",
+            "[00:10:00] <JohnDoe> test message",
+            "# Log closed: 2017-10-06 00:10:00 +0200"
+        ));
+        assert_eq!(repaired.fixes, vec!(
+            Fix {
+                line: 1,
+                damage: Damage::DuplicateHeader,
+                removed: vec!("# Log started: 2017-10-05 23:40:00 +0200".to_owned()),
+                added: vec!()
+            },
+            Fix { line: 5, damage: Damage::Bytes, removed: vec!(), added: vec!() },
+            Fix {
+                line: 6,
+                damage: Damage::TrailingGarbage,
+                removed: vec!("\u{13}7garbage".to_owned()),
+                added: vec!()
+            },
+            Fix {
+                line: 7,
+                damage: Damage::MissingClose,
+                removed: vec!(),
+                added: vec!("# Log closed: 2017-10-06 00:10:00 +0200".to_owned())
+            }
+        ));
+
+        let mut report = Vec::new();
+        repaired.write_report(&mut report).unwrap();
+        assert!(String::from_utf8(report).unwrap().starts_with(
+            "@@ line 1: duplicate log start\n-# Log started: 2017-10-05 23:40:00 +0200\n"));
+    }
+}
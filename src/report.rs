@@ -0,0 +1,304 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use csv::Writer;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use errors::Result;
+use models::{Channel, Subscription};
+use models::types::{SubscriptionKind, SubscriptionTier};
+use parser::{AnomalyKind, RejectReason, RejectedLine, TimestampAnomaly};
+use schema::{channels, sessions, subscriptions};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+/// Subscription activity of a channel on one day
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubscriptionDay {
+    pub channel: String,
+    pub day: NaiveDate,
+    pub subs: i64,
+    pub resubs: i64,
+    pub gifts: i64,
+    pub tier1: i64,
+    pub tier2: i64,
+    pub tier3: i64,
+    pub prime: i64
+}
+
+/// Counts the archived subscriptions of every channel per day, ordered by channel and day.
+///
+/// Days are counted in `zone`, or in UTC if none is given.
+pub fn subscriptions_per_day(connection: &PgConnection, zone: Option<&Tz>)
+    -> Result<Vec<SubscriptionDay>> {
+    let rows: Vec<(Subscription, Channel)> = subscriptions::table
+        .inner_join(channels::table)
+        .load(connection)?;
+    Ok(count_subscriptions(&rows, zone))
+}
+
+/// Counts subscriptions per channel and day in `zone`, ordered by channel and day
+fn count_subscriptions(rows: &[(Subscription, Channel)], zone: Option<&Tz>) -> Vec<SubscriptionDay> {
+    let mut days: BTreeMap<(&str, NaiveDate), SubscriptionDay> = BTreeMap::new();
+    for &(ref subscription, ref channel) in rows {
+        let sent_at = Utc.from_utc_datetime(&subscription.sent_at);
+        let day = match zone {
+            Some(zone) => sent_at.with_timezone(zone).date().naive_local(),
+            None => sent_at.date().naive_utc()
+        };
+        let counts = days.entry((channel.name.as_str(), day)).or_insert_with(|| SubscriptionDay {
+            channel: channel.name.clone(),
+            day,
+            subs: 0,
+            resubs: 0,
+            gifts: 0,
+            tier1: 0,
+            tier2: 0,
+            tier3: 0,
+            prime: 0
+        });
+        match subscription.kind {
+            SubscriptionKind::Sub => counts.subs += 1,
+            SubscriptionKind::Resub => counts.resubs += 1,
+            SubscriptionKind::Gift => counts.gifts += 1,
+        }
+        match subscription.tier {
+            SubscriptionTier::Tier1 => counts.tier1 += 1,
+            SubscriptionTier::Tier2 => counts.tier2 += 1,
+            SubscriptionTier::Tier3 => counts.tier3 += 1,
+            SubscriptionTier::Prime => counts.prime += 1,
+        }
+    }
+    days.into_iter().map(|(_, day)| day).collect()
+}
+
+/// Time span a logging session recorded a channel
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSpan {
+    pub channel: String,
+    pub started_at: NaiveDateTime,
+    pub ended_at: Option<NaiveDateTime>,
+    pub last_seen_at: NaiveDateTime
+}
+
+/// A period in which a channel was either recorded or not
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageInterval {
+    pub channel: String,
+    pub covered: bool,
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+    /// Whether the recording ended without the log being closed, so it may have ended later
+    pub unclosed: bool
+}
+
+/// Loads the spans of all sessions with a known channel
+pub fn session_spans(connection: &PgConnection) -> Result<Vec<SessionSpan>> {
+    let rows: Vec<(String, NaiveDateTime, Option<NaiveDateTime>, Option<NaiveDateTime>)> = sessions::table
+        .inner_join(channels::table)
+        .select((channels::name, sessions::started_at, sessions::ended_at, sessions::last_seen_at))
+        .load(connection)?;
+    Ok(rows.into_iter()
+        .map(|(channel, started_at, ended_at, last_seen_at)| SessionSpan {
+            channel,
+            started_at,
+            ended_at,
+            last_seen_at: last_seen_at.unwrap_or(started_at)
+        })
+        .collect())
+}
+
+/// Builds the timeline of covered and uncovered periods of every channel.
+///
+/// Overlapping sessions, for example from different recorders, are merged into one covered
+/// interval. A session which was never closed is assumed to have ended with its last line. The
+/// result is ordered by channel and time, with a gap between every two covered intervals.
+pub fn coverage(mut spans: Vec<SessionSpan>) -> Vec<CoverageInterval> {
+    spans.sort_by(|a, b| (&a.channel, a.started_at).cmp(&(&b.channel, b.started_at)));
+    let mut intervals: Vec<CoverageInterval> = Vec::new();
+
+    for span in spans {
+        let end = span.ended_at.unwrap_or(span.last_seen_at).max(span.started_at);
+        let unclosed = span.ended_at.is_none();
+
+        let previous_end = match intervals.last_mut() {
+            Some(ref mut last) if last.channel == span.channel => {
+                if span.started_at <= last.to {
+                    if end > last.to {
+                        last.to = end;
+                        last.unclosed = unclosed;
+                    } else if end == last.to {
+                        last.unclosed = last.unclosed && unclosed;
+                    }
+                    continue;
+                }
+                Some(last.to)
+            },
+            _ => None
+        };
+
+        if let Some(previous_end) = previous_end {
+            intervals.push(CoverageInterval {
+                channel: span.channel.clone(),
+                covered: false,
+                from: previous_end,
+                to: span.started_at,
+                unclosed: false
+            });
+        }
+        intervals.push(CoverageInterval {
+            channel: span.channel,
+            covered: true,
+            from: span.started_at,
+            to: end,
+            unclosed
+        });
+    }
+    intervals
+}
+
+/// Converts the UTC times of coverage intervals into the local time of `zone`
+pub fn localize(intervals: &mut [CoverageInterval], zone: &Tz) {
+    for interval in intervals {
+        interval.from = Utc.from_utc_datetime(&interval.from).with_timezone(zone).naive_local();
+        interval.to = Utc.from_utc_datetime(&interval.to).with_timezone(zone).naive_local();
+    }
+}
+
+/// A timestamp anomaly together with the log file it was found in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnomalyRecord {
+    pub file: String,
+    pub line: usize,
+    pub previous: DateTime<FixedOffset>,
+    pub new: DateTime<FixedOffset>,
+    pub kind: AnomalyKind
+}
+
+impl AnomalyRecord {
+    pub fn new(file: &Path, anomaly: &TimestampAnomaly) -> AnomalyRecord {
+        AnomalyRecord {
+            file: file.display().to_string(),
+            line: anomaly.line,
+            previous: anomaly.previous,
+            new: anomaly.new,
+            kind: anomaly.kind
+        }
+    }
+}
+
+/// A rejected line together with the log file it was found in
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RejectRecord {
+    pub file: String,
+    pub line: usize,
+    pub reason: RejectReason,
+    pub text: String
+}
+
+impl RejectRecord {
+    pub fn new(file: &Path, rejected: &RejectedLine) -> RejectRecord {
+        RejectRecord {
+            file: file.display().to_string(),
+            line: rejected.line,
+            reason: rejected.reason,
+            text: rejected.text.clone()
+        }
+    }
+}
+
+/// Writes report rows as CSV, with a header row named after the fields
+pub fn write_csv<W: Write, T: Serialize>(output: W, rows: &[T]) -> Result<()> {
+    let mut writer = Writer::from_writer(output);
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd(2017, 10, 5).and_hms(hour, minute, 0)
+    }
+
+    fn span(channel: &str, started_at: NaiveDateTime, ended_at: Option<NaiveDateTime>,
+            last_seen_at: NaiveDateTime) -> SessionSpan {
+        SessionSpan { channel: channel.to_owned(), started_at, ended_at, last_seen_at }
+    }
+
+    fn interval(channel: &str, covered: bool, from: NaiveDateTime, to: NaiveDateTime,
+                unclosed: bool) -> CoverageInterval {
+        CoverageInterval { channel: channel.to_owned(), covered, from, to, unclosed }
+    }
+
+    fn subscription(channel: &str, sent_at: NaiveDateTime, kind: SubscriptionKind,
+                    tier: SubscriptionTier) -> (Subscription, Channel) {
+        let subscription = Subscription {
+            id: 0,
+            channel_id: 0,
+            subscriber_id: 0,
+            recipient_id: None,
+            kind,
+            tier,
+            months: 1,
+            message: None,
+            sent_at,
+            import_id: None
+        };
+        (subscription, Channel { id: 0, name: channel.to_owned() })
+    }
+
+    #[test]
+    fn count_subscriptions_per_local_day() {
+        let rows = vec!(
+            subscription("#b", at(12, 0), SubscriptionKind::Sub, SubscriptionTier::Prime),
+            subscription("#a", at(21, 0), SubscriptionKind::Resub, SubscriptionTier::Tier2),
+            subscription("#a", at(23, 0), SubscriptionKind::Gift, SubscriptionTier::Tier1),
+            subscription("#a", at(10, 0), SubscriptionKind::Sub, SubscriptionTier::Tier1),
+        );
+
+        let days = count_subscriptions(&rows, None);
+        assert_eq!(days.iter().map(|d| (d.channel.as_str(), d.day, d.subs, d.resubs, d.gifts)).collect::<Vec<_>>(),
+                   vec!(("#a", at(0, 0).date(), 1, 1, 1), ("#b", at(0, 0).date(), 1, 0, 0)));
+        assert_eq!((days[0].tier1, days[0].tier2, days[1].prime), (2, 1, 1));
+
+        // 23:00 UTC is already the next day in Berlin
+        let days = count_subscriptions(&rows, Some(&"Europe/Berlin".parse().unwrap()));
+        let next_day = NaiveDate::from_ymd(2017, 10, 6);
+        assert_eq!(days.iter().map(|d| (d.channel.as_str(), d.day, d.subs + d.resubs + d.gifts)).collect::<Vec<_>>(),
+                   vec!(("#a", at(0, 0).date(), 2), ("#a", next_day, 1), ("#b", at(0, 0).date(), 1)));
+    }
+
+    #[test]
+    fn gaps_between_sessions() {
+        let intervals = coverage(vec!(
+            span("#b", at(10, 0), Some(at(11, 0)), at(11, 0)),
+            span("#a", at(14, 0), None, at(14, 30)),
+            span("#a", at(10, 0), Some(at(12, 0)), at(12, 0)),
+        ));
+
+        assert_eq!(intervals, vec!(
+            interval("#a", true, at(10, 0), at(12, 0), false),
+            interval("#a", false, at(12, 0), at(14, 0), false),
+            interval("#a", true, at(14, 0), at(14, 30), true),
+            interval("#b", true, at(10, 0), at(11, 0), false),
+        ));
+    }
+
+    #[test]
+    fn overlapping_sessions_are_merged() {
+        let intervals = coverage(vec!(
+            span("#a", at(10, 0), None, at(11, 0)),
+            span("#a", at(10, 30), Some(at(12, 0)), at(12, 0)),
+            span("#a", at(11, 0), Some(at(11, 30)), at(11, 30)),
+            span("#a", at(12, 0), None, at(12, 0)),
+        ));
+
+        assert_eq!(intervals, vec!(interval("#a", true, at(10, 0), at(12, 0), false)));
+    }
+}
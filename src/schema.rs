@@ -5,16 +5,87 @@ table! {
     }
 }
 
+table! {
+    events (id) {
+        id -> Int4,
+        channel_id -> Nullable<Int4>,
+        sent_at -> Timestamp,
+        message -> Text,
+        import_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    imports (id) {
+        id -> Int4,
+        source_path -> Varchar,
+        content_hash -> Varchar,
+        byte_size -> Int8,
+        started_at -> Timestamp,
+        finished_at -> Nullable<Timestamp>,
+        parser -> Varchar,
+        message_count -> Int4,
+        duplicate_count -> Int4,
+    }
+}
+
 table! {
     messages (id) {
         id -> Int4,
         user_id -> Int4,
         channel_id -> Int4,
         message -> Text,
+        sent_at -> Timestamptz,
         flags -> Array<SmallInt>,
+        occurrence -> SmallInt,
+        import_id -> Nullable<Int4>,
+        deleted_at -> Nullable<Timestamptz>,
+        deleted_by_action -> Nullable<Int4>,
+        kind -> SmallInt,
+        session_id -> Nullable<Int4>,
+        utc_offset -> Nullable<Int4>,
+    }
+}
+
+table! {
+    moderation_actions (id) {
+        id -> Int4,
+        channel_id -> Int4,
+        target_id -> Int4,
+        moderator_id -> Nullable<Int4>,
+        kind -> SmallInt,
+        duration -> Nullable<Int4>,
+        reason -> Nullable<Text>,
+        sent_at -> Timestamp,
+        import_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    sessions (id) {
+        id -> Int4,
+        channel_id -> Nullable<Int4>,
+        started_at -> Timestamp,
+        ended_at -> Nullable<Timestamp>,
+        last_seen_at -> Nullable<Timestamp>,
+        message_count -> Int4,
+        import_id -> Nullable<Int4>,
+        source_path -> Nullable<Text>,
+    }
+}
+
+table! {
+    subscriptions (id) {
+        id -> Int4,
+        channel_id -> Int4,
+        subscriber_id -> Int4,
+        recipient_id -> Nullable<Int4>,
+        kind -> SmallInt,
+        tier -> SmallInt,
+        months -> Int4,
+        message -> Nullable<Text>,
         sent_at -> Timestamp,
-        prime -> Bool,
-        moderator -> Bool,
+        import_id -> Nullable<Int4>,
     }
 }
 
@@ -25,5 +96,34 @@ table! {
     }
 }
 
+joinable!(events -> channels (channel_id));
+joinable!(events -> imports (import_id));
 joinable!(messages -> users (user_id));
 joinable!(messages -> channels (channel_id));
+joinable!(messages -> imports (import_id));
+joinable!(messages -> moderation_actions (deleted_by_action));
+joinable!(messages -> sessions (session_id));
+joinable!(moderation_actions -> channels (channel_id));
+joinable!(moderation_actions -> users (target_id));
+joinable!(moderation_actions -> imports (import_id));
+joinable!(sessions -> channels (channel_id));
+joinable!(sessions -> imports (import_id));
+joinable!(subscriptions -> channels (channel_id));
+joinable!(subscriptions -> users (subscriber_id));
+joinable!(subscriptions -> imports (import_id));
+
+/// Tables which differ in the SQLite schema
+pub mod sqlite {
+    table! {
+        messages (id) {
+            id -> Integer,
+            user_id -> Integer,
+            channel_id -> Integer,
+            message -> Text,
+            flags -> Text,
+            sent_at -> Timestamp,
+            kind -> SmallInt,
+            utc_offset -> Nullable<Integer>,
+        }
+    }
+}